@@ -1,100 +1,227 @@
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::vec::Vec;
+use ranges;
+use table::TableFormat;
+
+// A node of a DFA, for stepping through it with DFA::next_state.
+// DFA::dead_state (which isn't a real node) stands for every input that can no longer match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StateId(usize);
+
+impl StateId {
+    pub(crate) const DEAD: StateId = StateId(usize::MAX);
+
+    pub(crate) fn new(id: usize) -> Self {
+        StateId(id)
+    }
+
+    pub fn as_usize(self) -> usize {
+        self.0
+    }
+}
 
 pub struct NFA {
-    regex: String,
-    flags: HashSet<char>,
-    data: HashMap<i8, NfaNode>,
-    range: HashSet<char>,
+    pub(crate) regex: String,
+    pub(crate) flags: HashSet<char>,
+    pub(crate) data: HashMap<usize, NfaNode>,
+    // Name of each capture group (if it has one). Group 0 is the whole match.
+    pub(crate) capture_names: Vec<Option<String>>,
 }
 
-struct NfaNode {
-    id: i8,
-    is_terminal: bool,
-    paths: Vec<(char, &'static i8)>,
+pub(crate) struct NfaNode {
+    pub(crate) id: usize,
+    pub(crate) is_terminal: bool,
+    // Connections for the characters low..=high, as (low, high, to). They can overlap.
+    pub(crate) paths: Vec<(char, char, usize)>,
+    // Empty (ε) connections, in the order a backtracking matcher would try them.
+    pub(crate) epsilons: Vec<usize>,
+    // Entering this node records the position in this capture slot.
+    // Group n starts at slot 2n and ends at slot 2n + 1.
+    pub(crate) capture: Option<usize>,
+    // When several regexes are combined into one automaton, the ones this terminal node accepts.
+    pub(crate) patterns: BTreeSet<usize>,
 }
 
 impl NFA {
-    fn new() -> Self {
+    pub(crate) fn new(regex: String, flags: HashSet<char>) -> Self {
         NFA {
-            regex: String::new(),
-            flags: HashSet::new(),
+            regex,
+            flags,
             data: HashMap::from([(0, NfaNode::new(0))]),
-            range: HashSet::new(),
+            capture_names: vec![None],
         }
     }
 
-    fn get_front(&self) -> Option<&NfaNode> {
-        self.data.get(&0)
+    pub(crate) fn clone(&self) -> Self {
+        NFA {
+            regex: self.regex.clone(),
+            flags: self.flags.clone(),
+            data: self.data.iter().map(|(id, node)| (*id, node.clone())).collect(),
+            capture_names: self.capture_names.clone(),
+        }
+    }
+
+    pub(crate) fn add_node(&mut self, node: NfaNode) {
+        self.data.insert(node.id, node);
+    }
+
+    // Adds an empty node and returns its id.
+    pub(crate) fn new_node(&mut self) -> usize {
+        let id = self.data.len();
+        self.add_node(NfaNode::new(id));
+        id
+    }
+
+    pub(crate) fn add_path(&mut self, from: usize, low: char, high: char, to: usize) {
+        self.data.get_mut(&from).unwrap().paths.push((low, high, to));
+    }
+
+    pub(crate) fn add_epsilon(&mut self, from: usize, to: usize) {
+        self.data.get_mut(&from).unwrap().epsilons.push(to);
+    }
+
+    // Returns every node reachable from id through ε connections (including id itself),
+    // in the order a backtracking matcher would try them.
+    pub(crate) fn epsilon_closure(&self, id: usize) -> Vec<usize> {
+        let mut closure: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = vec![id];
+        let mut visited: HashSet<usize> = HashSet::new();
+        while let Some(cur) = stack.pop() {
+            if !visited.insert(cur) {
+                continue;
+            }
+            closure.push(cur);
+            for next in self.data[&cur].epsilons.iter().rev() {
+                if !visited.contains(next) {
+                    stack.push(*next);
+                }
+            }
+        }
+        closure
     }
 
-    fn add_node(&mut self, node: NfaNode) {
-        let id = node.id;
-        self.data.insert(id, node.clone());
-        for (ch, _) in node.paths {
-            if !self.range.contains(&ch) {
-                self.range.insert(ch);
+    // Combines several NFAs into one that accepts the strings of any of them, with a new front node
+    // ε-connected to each of their fronts. Terminal nodes remember the index of the NFA they came from.
+    pub(crate) fn combine(nfas: Vec<NFA>) -> NFA {
+        let regex = nfas.iter().map(|nfa| nfa.regex.as_str()).collect::<Vec<&str>>().join(", ");
+        let mut combined = NFA::new(regex, HashSet::new());
+        for (pattern, nfa) in nfas.into_iter().enumerate() {
+            let offset = combined.data.len();
+            for (id, mut node) in nfa.data {
+                node.id = id + offset;
+                node.capture = None;
+                for (_, _, to) in node.paths.iter_mut() {
+                    *to += offset;
+                }
+                for to in node.epsilons.iter_mut() {
+                    *to += offset;
+                }
+                if node.is_terminal {
+                    node.patterns = BTreeSet::from([pattern]);
+                }
+                combined.add_node(node);
             }
+            combined.add_epsilon(0, offset);
         }
+        combined
     }
 
+    // Builds the NFA that accepts every string of this one backwards.
+    // The new front node is ε-connected to what used to be the terminal nodes.
+    pub(crate) fn reverse(&self) -> NFA {
+        let mut reversed = NFA::new(self.regex.clone(), self.flags.clone());
+        reversed.capture_names = self.capture_names.clone();
+        let mut ids: Vec<&usize> = self.data.keys().collect();
+        ids.sort();
+        for id in &ids {
+            reversed.add_node(NfaNode::new(*id + 1));
+        }
+        for id in ids {
+            let node = &self.data[id];
+            if node.is_terminal {
+                reversed.add_epsilon(0, id + 1);
+            }
+            for (low, high, to) in &node.paths {
+                reversed.add_path(to + 1, *low, *high, id + 1);
+            }
+            for to in &node.epsilons {
+                reversed.add_epsilon(to + 1, id + 1);
+            }
+        }
+        reversed.data.get_mut(&1).unwrap().is_terminal = true;
+        reversed
+    }
 
+    // Lets the automaton skip over any prefix of the input before matching, by looping the front node
+    // on every character. The front node must not have any incoming paths.
+    pub(crate) fn unanchored(mut self) -> NFA {
+        self.add_path(0, '\0', char::MAX, 0);
+        self
+    }
 }
 
 impl NfaNode {
-    fn new(id: i8) -> Self {
+    pub(crate) fn new(id: usize) -> Self {
         NfaNode {
             id,
             is_terminal: false,
             paths: Vec::new(),
+            epsilons: Vec::new(),
+            capture: None,
+            patterns: BTreeSet::new(),
         }
     }
 
-    fn clone(&self) -> Self {
+    pub(crate) fn clone(&self) -> Self {
         NfaNode {
-            id: self.id.clone(),
-            is_terminal: self.is_terminal.clone(),
+            id: self.id,
+            is_terminal: self.is_terminal,
             paths: self.paths.clone(),
+            epsilons: self.epsilons.clone(),
+            capture: self.capture,
+            patterns: self.patterns.clone(),
         }
     }
 
-    fn is_dfa(&self) -> bool {
-        let mut unique: HashSet<char> = HashSet::new();
-        for (ch, _) in &self.paths {
-            if unique.contains(ch) { return false; }
-            if *ch == '\0' { return false;}
-            unique.insert(*ch);
-        } true
-    }
-
-    fn add_path(&mut self, ch: char, node_id: &'static i8) {
-        self.paths.push((ch, node_id));
+    // Returns true if the node has no ε connections and no two of its connections share a character.
+    pub(crate) fn is_dfa(&self) -> bool {
+        let mut sorted: Vec<(char, char)> = self.paths.iter().map(|(low, high, _)| (*low, *high)).collect();
+        sorted.sort();
+        self.epsilons.is_empty() && sorted.windows(2).all(|pair| pair[0].1 < pair[1].0)
     }
-
-
 }
 
 pub struct DFA {
-    regex: String,
-    flags: HashSet<char>,
-    data: HashMap<i8, DfaNode>,
-    range: HashSet<char>,
+    pub(crate) regex: String,
+    pub(crate) flags: HashSet<char>,
+    pub(crate) data: HashMap<usize, DfaNode>,
 }
 
-struct DfaNode {
-    id: i8,
-    is_terminal: bool,
-    paths: HashMap<char, &'static i8>
+pub(crate) struct DfaNode {
+    pub(crate) id: usize,
+    pub(crate) is_terminal: bool,
+    // Connections for the characters low..=high, as sorted, non-overlapping (low, high, to)
+    pub(crate) paths: Vec<(char, char, usize)>,
+    // When several regexes are combined into one automaton, the ones this terminal node accepts.
+    pub(crate) patterns: BTreeSet<usize>,
 }
 
 impl DFA {
-    fn new(regex: String, flags: HashSet<char>) -> Self {
+    pub(crate) fn new(regex: String, flags: HashSet<char>) -> Self {
         DFA {
             regex,
             flags,
             data: HashMap::new(),
-            range: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn clone(&self) -> Self {
+        DFA {
+            regex: self.regex.clone(),
+            flags: self.flags.clone(),
+            data: self.data.iter().map(|(id, node)| (*id, node.clone())).collect(),
         }
     }
 
@@ -102,106 +229,175 @@ impl DFA {
         self.data.get(&0)
     }
 
-    fn add_node(&mut self, node: DfaNode) {
-        self.data.insert(node.id, node.clone());
-        for (ch, _) in node.paths {
-            if !self.range.contains(&ch) {
-                self.range.insert(ch);
-            }
-        }
+    pub(crate) fn add_node(&mut self, node: DfaNode) {
+        self.data.insert(node.id, node);
     }
 
-    fn get_node(&self, id: &i8) -> Option<&DfaNode> {
-        self.data.get(id)
+    // Splits every character into sorted ranges that each node treats the same way: all the characters
+    // of a range lead to the same node (or are all rejected). Covers every character, so the ranges
+    // that no node connects are included too.
+    pub(crate) fn alphabet(&self) -> Vec<(char, char)> {
+        ranges::partition(self.data.values().flat_map(|node| node.paths.iter().map(|(low, high, _)| (*low, *high))))
     }
 
-    fn get_node_mut(&mut self, id: &i8) -> Option<&mut DfaNode> {
-        self.data.get_mut(id)
+    pub(crate) fn get_node(&self, id: &usize) -> Option<&DfaNode> {
+        self.data.get(id)
     }
 
     // Displaying each node and it's connections. '_' means there is no connection for that character.
-    fn display(&self) {
-        println!("{}{}", self.regex, self.to_string(true));
-    }
-
-    fn to_string(&self, in_color: bool) -> String {
-        let mut output = String::new();
-        let keys: Vec<char> = self.range.iter().map(|x: &char| *x).collect::<Vec<char>>();
-        output += "  ";
-        for ch in &self.range {
-            output += &format!("  {}", ch);
-        }
-        for (id, node) in &self.data {
-            output += "\n";
-            if node.is_terminal && in_color {
-                output += &format!("\x1b[0;31m{}\x1b[0m", id);
-            } else {
-                output += &format!("{}", id);
-            }
-            for ch in &keys {
-                if node.paths.contains_key(ch) {
-                    output += &format!("  {}", node.follow(ch));
-                } else {
-                    output += "  _";
-                }
-            }
-
-        }
-        output
+    pub fn display(&self) {
+        println!("{}\n{}", self.regex, self.to_table(TableFormat::Ansi));
     }
 
     // Returns true if the input string matches the DFA.
     pub fn matches(&self, input: &str) -> bool {
         let mut current_node = self.get_front().unwrap();
         for ch in input.chars() {
-            if current_node.paths.contains_key(&ch) {
-                current_node = self.get_node(current_node.follow(&ch)).unwrap();
-            } else {
-                return false;
+            match current_node.follow(&ch) {
+                Some(next_id) => current_node = self.get_node(&next_id).unwrap(),
+                None => return false,
             }
         }
         current_node.is_terminal
     }
+
+    // The state before any input has been read.
+    pub fn start_state(&self) -> StateId {
+        StateId(0)
+    }
+
+    // The state that every input ends up in once it can no longer match.
+    pub fn dead_state(&self) -> StateId {
+        StateId::DEAD
+    }
+
+    // Returns the state after reading ch in the given state. Stays in the dead state once it has been reached.
+    // Input that comes as bytes is read with the DFA from to_bytes instead.
+    pub fn next_state(&self, state: StateId, ch: char) -> StateId {
+        self.get_node(&state.0)
+            .and_then(|node| node.follow(&ch))
+            .map_or(StateId::DEAD, StateId)
+    }
+
+    // Returns true if the input read so far matches.
+    pub fn is_match_state(&self, state: StateId) -> bool {
+        self.get_node(&state.0).is_some_and(|node| node.is_terminal)
+    }
+
+    // Returns true if no more input can make a match, which is also the case for a node without a way out.
+    pub fn is_dead_state(&self, state: StateId) -> bool {
+        match self.get_node(&state.0) {
+            None => true,
+            Some(node) => !node.is_terminal && node.paths.iter().all(|(_, _, to)| *to == node.id),
+        }
+    }
+
+    // Returns the state after the end of the input: the same state if the input matches, and the dead state otherwise.
+    pub fn eoi_transition(&self, state: StateId) -> StateId {
+        if self.is_match_state(state) { state } else { StateId::DEAD }
+    }
 }
 
 impl DfaNode {
-    fn new(id: i8) -> Self {
+    pub(crate) fn new(id: usize) -> Self {
         DfaNode {
             id,
             is_terminal: false,
-            paths: HashMap::new(),
+            paths: Vec::new(),
+            patterns: BTreeSet::new(),
         }
     }
 
-    fn clone(&self) -> Self {
+    pub(crate) fn clone(&self) -> Self {
         DfaNode {
-            id: self.id.clone(),
-            is_terminal: self.is_terminal.clone(),
+            id: self.id,
+            is_terminal: self.is_terminal,
             paths: self.paths.clone(),
+            patterns: self.patterns.clone(),
         }
     }
 
-    fn add_path(&mut self, ch: char, node_id: &'static i8) {
-        self.paths.insert(ch, node_id);
-    }
-
-    fn from(nfa_node: NfaNode) -> Self {
-        let mut paths: HashMap<char, &'static i8> = HashMap::new();
-        for (ch, node_id) in nfa_node.paths {
-            if !paths.contains_key(&ch) {
-                paths.insert(ch, node_id);
+    // Connects the characters low..=high, which must not have a connection yet, to node_id.
+    // Merges it with the neighbouring ranges when they go to the same node.
+    pub(crate) fn add_path(&mut self, low: char, high: char, node_id: usize) {
+        let index = self.paths.partition_point(|(other, _, _)| *other < low);
+        self.paths.insert(index, (low, high, node_id));
+        if index + 1 < self.paths.len() {
+            let (next_low, next_high, next_id) = self.paths[index + 1];
+            if next_id == node_id && ranges::next_char(high) == Some(next_low) {
+                self.paths[index].1 = next_high;
+                self.paths.remove(index + 1);
             }
         }
-        DfaNode {
-            id: nfa_node.id,
-            is_terminal: nfa_node.is_terminal,
-            paths,
+        if index > 0 {
+            let (_, prev_high, prev_id) = self.paths[index - 1];
+            if prev_id == node_id && ranges::next_char(prev_high) == Some(low) {
+                self.paths[index - 1].1 = self.paths[index].1;
+                self.paths.remove(index);
+            }
         }
     }
 
-    fn follow(&self, ch: &char) -> &'static i8 {
-        self.paths.get(&ch).unwrap()
+    // Only used on nodes that pass is_dfa.
+    pub(crate) fn from(nfa_node: NfaNode) -> Self {
+        let mut node = DfaNode::new(nfa_node.id);
+        node.is_terminal = nfa_node.is_terminal;
+        node.patterns = nfa_node.patterns;
+        for (low, high, to) in nfa_node.paths {
+            node.add_path(low, high, to);
+        }
+        node
     }
 
+    // Returns the node that ch leads to, or None if the input is rejected.
+    pub(crate) fn follow(&self, ch: &char) -> Option<usize> {
+        ranges::find(&self.paths, *ch).map(|index| self.paths[index].2)
+    }
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+
+    #[test]
+    fn test_state_stepping() {
+        let dfa = compile("/ab*/");
+        let mut state = dfa.start_state();
+        assert!(!dfa.is_match_state(state));
+        state = dfa.next_state(state, 'a');
+        assert!(dfa.is_match_state(state));
+        assert_eq!(dfa.eoi_transition(state), state);
+        assert_eq!(dfa.eoi_transition(dfa.start_state()), dfa.dead_state());
+
+        let dead = dfa.next_state(state, 'c');
+        assert!(dfa.is_dead_state(dead));
+        assert_eq!(dfa.next_state(dead, 'b'), dead);
+        assert!(!dfa.is_match_state(dead));
+        assert!(!dfa.is_dead_state(state));
+    }
+
+    #[test]
+    fn test_state_stepping_tokenizer() {
+        // finds the longest prefix of each token, the way a tokenizer would
+        let dfa = compile(r"/\d+|[a-z]+/");
+        let input = "abc123x";
+        let mut tokens: Vec<&str> = Vec::new();
+        let mut start = 0;
+        while start < input.len() {
+            let mut state = dfa.start_state();
+            let mut end = start;
+            for (idx, ch) in input[start..].char_indices() {
+                state = dfa.next_state(state, ch);
+                if dfa.is_dead_state(state) {
+                    break;
+                }
+                if dfa.is_match_state(state) {
+                    end = start + idx + ch.len_utf8();
+                }
+            }
+            tokens.push(&input[start..end]);
+            start = end;
+        }
+        assert_eq!(tokens, vec!["abc", "123", "x"]);
+    }
+}
@@ -0,0 +1,214 @@
+use random::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::MulAssign;
+use std::ops::SubAssign;
+
+// An unsigned integer of any size, for counting the strings a DFA accepts.
+// Only has the few operations the counting and sampling need.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigUint {
+    // Base 2^32 digits, least significant first, without zeros at the end
+    digits: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        BigUint { digits: Vec::new() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    // Returns the value if it fits in a u64.
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.digits.len() {
+            0 => Some(0),
+            1 => Some(self.digits[0] as u64),
+            2 => Some(self.digits[0] as u64 | (self.digits[1] as u64) << 32),
+            _ => None,
+        }
+    }
+
+    // Number of bits needed to write the value, 0 for zero.
+    pub fn bits(&self) -> usize {
+        match self.digits.last() {
+            None => 0,
+            Some(top) => self.digits.len() * 32 - top.leading_zeros() as usize,
+        }
+    }
+
+    // Returns a uniformly random value in 0..self, which must not be zero.
+    // Draws numbers with as many bits as self until one is small enough, which takes 2 tries at most on average.
+    pub fn random_below<R: Rng>(&self, rng: &mut R) -> BigUint {
+        assert!(!self.is_zero(), "random_below needs a bound greater than zero");
+        let bits = self.bits();
+        loop {
+            let mut digits: Vec<u32> = (0..self.digits.len()).map(|_| rng.next_u64() as u32).collect();
+            let extra = self.digits.len() * 32 - bits;
+            if let Some(top) = digits.last_mut() {
+                *top &= u32::MAX >> extra;
+            }
+            let value = BigUint::from_digits(digits);
+            if value < *self {
+                return value;
+            }
+        }
+    }
+
+    fn from_digits(mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigUint { digits }
+    }
+
+    // Divides by a small number in place and returns the remainder.
+    pub(crate) fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder: u64 = 0;
+        for digit in self.digits.iter_mut().rev() {
+            let current = remainder << 32 | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        *self = BigUint::from_digits(std::mem::take(&mut self.digits));
+        remainder as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        BigUint::from_digits(vec![value as u32, (value >> 32) as u32])
+    }
+}
+
+impl<'a> AddAssign<&'a BigUint> for BigUint {
+    fn add_assign(&mut self, other: &'a BigUint) {
+        let mut carry: u64 = 0;
+        for i in 0..self.digits.len().max(other.digits.len()) {
+            if i == self.digits.len() {
+                self.digits.push(0);
+            }
+            let sum = self.digits[i] as u64 + *other.digits.get(i).unwrap_or(&0) as u64 + carry;
+            self.digits[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+}
+
+impl<'a> Add<&'a BigUint> for BigUint {
+    type Output = BigUint;
+
+    fn add(mut self, other: &'a BigUint) -> BigUint {
+        self += other;
+        self
+    }
+}
+
+impl MulAssign<u32> for BigUint {
+    fn mul_assign(&mut self, factor: u32) {
+        let mut carry: u64 = 0;
+        for digit in self.digits.iter_mut() {
+            let product = *digit as u64 * factor as u64 + carry;
+            *digit = product as u32;
+            carry = product >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+        *self = BigUint::from_digits(std::mem::take(&mut self.digits));
+    }
+}
+
+// Panics if other is bigger than self.
+impl<'a> SubAssign<&'a BigUint> for BigUint {
+    fn sub_assign(&mut self, other: &'a BigUint) {
+        assert!(*self >= *other, "BigUint subtraction underflowed");
+        let mut borrow: i64 = 0;
+        for i in 0..self.digits.len() {
+            let mut difference = self.digits[i] as i64 - *other.digits.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if difference < 0 {
+                difference += 1 << 32;
+                borrow = 1;
+            }
+            self.digits[i] = difference as u32;
+        }
+        *self = BigUint::from_digits(std::mem::take(&mut self.digits));
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.digits.len().cmp(&other.digits.len())
+            .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Splits off 9 decimal digits at a time
+        let mut rest = self.clone();
+        let mut parts: Vec<u32> = Vec::new();
+        while !rest.is_zero() {
+            parts.push(rest.div_rem_small(1_000_000_000));
+        }
+        write!(f, "{}", parts.pop().unwrap())?;
+        for part in parts.iter().rev() {
+            write!(f, "{:09}", part)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::XorShift;
+
+    #[test]
+    fn test_big_uint_arithmetic() {
+        let mut value = BigUint::from(u64::MAX);
+        value += &BigUint::from(1);
+        assert_eq!(value.to_u64(), None);
+        assert_eq!(value.bits(), 65);
+        assert_eq!(value.to_string(), "18446744073709551616");
+        value -= &BigUint::from(2);
+        assert_eq!(value.to_u64(), Some(u64::MAX - 1));
+        assert!(BigUint::from(3) < BigUint::from(1 << 40));
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_007).to_string(), "1000000007");
+        let mut product = BigUint::from(u64::MAX);
+        product *= 1 << 16;
+        assert_eq!(product.to_string(), "1208925819614629174640640");
+        product *= 0;
+        assert!(product.is_zero());
+    }
+
+    #[test]
+    fn test_random_below() {
+        let mut rng = XorShift::new(7);
+        let bound = BigUint::from(10);
+        let mut seen = [false; 10];
+        for _ in 0..200 {
+            let value = bound.random_below(&mut rng).to_u64().unwrap();
+            seen[value as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
@@ -0,0 +1,484 @@
+use automata::StateId;
+use automata::DFA;
+use error::Error;
+use parse_regex::raw_byte;
+use parse_regex::NO_UNICODE_FLAG;
+use parse_regex::RAW_BYTES;
+use ranges;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use steps::step2;
+use steps::step4;
+use steps::try_step1;
+use steps::try_step3;
+use steps::Limits;
+
+// A regex that searches &[u8] instead of &str, so it works on binary data and skips decoding UTF-8.
+//
+// It is built from the same DFAs as Regex. Each character is then replaced by the bytes of its
+// UTF-8 encoding, where runs of characters become byte ranges, and the result is made deterministic
+// again over bytes. Without Unicode, '.' and negated classes match any single byte as well.
+// Matches are leftmost-longest, found with a reverse and a forward DFA like in Regex.
+pub struct Regex {
+    regex: String,
+    unicode: bool,
+    forward: ByteDFA,
+    reverse: ByteDFA,
+}
+
+// A single match, where start and end are offsets into the searched bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h [u8],
+    pub start: usize,
+    pub end: usize,
+}
+
+// Iterator over the non-overlapping matches, returned by Regex::find_iter.
+pub struct Matches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h [u8],
+    starts: Vec<usize>,
+    next_start: usize,
+    last_end: Option<usize>,
+    pos: usize,
+}
+
+impl Regex {
+    // @param regex looks like: "/[a-z]+/i", the same as for the Regex over &str.
+    // Panics if the regex isn't valid or goes over the default limits of RegexBuilder.
+    pub fn new(regex: &str) -> Self {
+        Regex::try_new(regex).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like new, but '.', negated classes and \D, \W and \S also match bytes that aren't ASCII,
+    // one byte at a time, and '.' with the s flag matches any byte.
+    pub fn without_unicode(regex: &str) -> Self {
+        Regex::try_without_unicode(regex).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like new, but returns an error instead of panicking.
+    pub fn try_new(regex: &str) -> Result<Self, Error> {
+        Regex::build(regex, true)
+    }
+
+    // Like without_unicode, but returns an error instead of panicking.
+    pub fn try_without_unicode(regex: &str) -> Result<Self, Error> {
+        Regex::build(regex, false)
+    }
+
+    fn build(regex: &str, unicode: bool) -> Result<Self, Error> {
+        let limits = Limits::default();
+        let flags: &[char] = if unicode { &[] } else { &[NO_UNICODE_FLAG] };
+        let nfa = try_step1(regex.to_string(), flags, &limits)?;
+        let reverse = step4(try_step3(step2(nfa.reverse()), limits.dfa_size)?);
+        let forward = step4(try_step3(step2(nfa), limits.dfa_size)?);
+        Ok(Regex {
+            regex: regex.to_string(),
+            unicode,
+            forward: ByteDFA::new(&ByteNfa::new(&forward, false), false),
+            reverse: ByteDFA::new(&ByteNfa::new(&reverse, true), true),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.regex
+    }
+
+    // Returns true if the regex matches the whole haystack.
+    pub fn matches(&self, haystack: &[u8]) -> bool {
+        let mut state: usize = 0;
+        for byte in haystack {
+            state = self.forward.next(state, *byte);
+            if state == DEAD {
+                return false;
+            }
+        }
+        self.forward.terminal[state]
+    }
+
+    // Returns true if the regex matches anywhere in the haystack.
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    pub fn find<'h>(&self, haystack: &'h [u8]) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    // Returns the leftmost-longest match that starts at or after pos.
+    pub fn find_at<'h>(&self, haystack: &'h [u8], pos: usize) -> Option<Match<'h>> {
+        let start = *self.match_starts(haystack, pos).first()?;
+        Some(self.longest_match_at(haystack, start))
+    }
+
+    // Returns an iterator over all non-overlapping matches, from left to right.
+    // An empty match that starts where the previous match ended is skipped.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> Matches<'r, 'h> {
+        Matches {
+            regex: self,
+            haystack,
+            starts: self.match_starts(haystack, 0),
+            next_start: 0,
+            last_end: None,
+            pos: 0,
+        }
+    }
+
+    // Runs the reverse DFA from the end of the haystack back to pos, and returns every position
+    // (in increasing order) where a match starts. With Unicode, matches never start inside a character.
+    fn match_starts(&self, haystack: &[u8], pos: usize) -> Vec<usize> {
+        let mut starts: Vec<usize> = Vec::new();
+        let mut state: usize = 0;
+        if self.reverse.terminal[state] {
+            starts.push(haystack.len());
+        }
+        for idx in (pos..haystack.len()).rev() {
+            state = self.reverse.next(state, haystack[idx]);
+            let inside_char = self.unicode && (0x80..0xC0).contains(&haystack[idx]);
+            if self.reverse.terminal[state] && !inside_char {
+                starts.push(idx);
+            }
+        }
+        starts.reverse();
+        starts
+    }
+
+    // Runs the forward DFA from start for as long as it can, and returns the longest match it saw.
+    fn longest_match_at<'h>(&self, haystack: &'h [u8], start: usize) -> Match<'h> {
+        let mut state: usize = 0;
+        let mut end = start;
+        for (idx, byte) in haystack[start..].iter().enumerate() {
+            state = self.forward.next(state, *byte);
+            if state == DEAD {
+                break;
+            }
+            if self.forward.terminal[state] {
+                end = start + idx + 1;
+            }
+        }
+        Match { haystack, start, end }
+    }
+}
+
+impl<'h> Match<'h> {
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.start..self.end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl<'r, 'h> Iterator for Matches<'r, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        loop {
+            while self.next_start < self.starts.len() && self.starts[self.next_start] < self.pos {
+                self.next_start += 1;
+            }
+            let start = *self.starts.get(self.next_start)?;
+            let found = self.regex.longest_match_at(self.haystack, start);
+
+            if found.is_empty() && self.last_end == Some(found.end) {
+                // Step over one byte so the same empty match isn't found again
+                self.pos = start + 1;
+                continue;
+            }
+            self.pos = found.end;
+            self.last_end = Some(found.end);
+            return Some(found);
+        }
+    }
+}
+
+// Marks a missing connection in a ByteDFA. It is the same as DFA::dead_state.
+const DEAD: usize = usize::MAX;
+
+// A DFA over the bytes of UTF-8, where every state has a row with the next state for each of the 256 bytes.
+// Made by DFA::to_bytes for input that is read one byte at a time, since a byte isn't a character
+// by itself: é is the two bytes C3 A9, and the state after C3 is halfway through it.
+pub struct ByteDFA {
+    transitions: Vec<[usize; 256]>,
+    terminal: Vec<bool>,
+}
+
+// An NFA over bytes, where every connection is labelled with a range of bytes.
+struct ByteNfa {
+    paths: Vec<Vec<(u8, u8, usize)>>,
+    terminal: Vec<bool>,
+}
+
+impl ByteNfa {
+    // Replaces every connection of the DFA by a chain of connections through new nodes,
+    // one for each byte of the UTF-8 encoding. The bytes are in reverse order for a reversed DFA.
+    fn new(dfa: &DFA, reverse: bool) -> Self {
+        let mut ids: Vec<usize> = dfa.data.keys().copied().collect();
+        ids.sort();
+        let index: HashMap<usize, usize> = ids.iter().enumerate().map(|(index, id)| (*id, index)).collect();
+        let raw_bytes = dfa.flags.contains(&NO_UNICODE_FLAG);
+        let mut nfa = ByteNfa {
+            paths: vec![Vec::new(); ids.len()],
+            terminal: ids.iter().map(|id| dfa.data[id].is_terminal).collect(),
+        };
+        for id in &ids {
+            let mut targets: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+            for (low, high, to) in &dfa.data[id].paths {
+                targets.entry(index[to]).or_default().push((*low, *high));
+            }
+            for (to, chars) in targets {
+                for mut sequence in byte_sequences(&chars, raw_bytes) {
+                    if reverse {
+                        sequence.reverse();
+                    }
+                    nfa.add_chain(index[id], &sequence, to);
+                }
+            }
+        }
+        nfa
+    }
+
+    fn add_chain(&mut self, from: usize, sequence: &[(u8, u8)], to: usize) {
+        let mut current = from;
+        for (i, (low, high)) in sequence.iter().enumerate() {
+            let next = if i + 1 == sequence.len() {
+                to
+            } else {
+                self.paths.push(Vec::new());
+                self.terminal.push(false);
+                self.paths.len() - 1
+            };
+            self.paths[current].push((*low, *high, next));
+            current = next;
+        }
+    }
+}
+
+impl ByteDFA {
+    // Subset construction over bytes. If unanchored is set, a match may start at any byte,
+    // so the front node is added to every set.
+    fn new(nfa: &ByteNfa, unanchored: bool) -> Self {
+        let mut dfa = ByteDFA { transitions: Vec::new(), terminal: Vec::new() };
+        let front: BTreeSet<usize> = BTreeSet::from([0]);
+        let mut ids: HashMap<BTreeSet<usize>, usize> = HashMap::from([(front.clone(), 0)]);
+        let mut queue: VecDeque<BTreeSet<usize>> = VecDeque::from([front]);
+        while let Some(set) = queue.pop_front() {
+            let mut targets: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); 256];
+            for member in &set {
+                for (low, high, to) in &nfa.paths[*member] {
+                    for byte in *low..=*high {
+                        targets[byte as usize].insert(*to);
+                    }
+                }
+            }
+            let mut row = [DEAD; 256];
+            for (byte, mut target) in targets.into_iter().enumerate() {
+                if unanchored {
+                    target.insert(0);
+                }
+                if target.is_empty() {
+                    continue;
+                }
+                let next_id = ids.len();
+                row[byte] = *ids.entry(target.clone()).or_insert_with(|| {
+                    queue.push_back(target);
+                    next_id
+                });
+            }
+            dfa.transitions.push(row);
+            dfa.terminal.push(set.iter().any(|member| nfa.terminal[*member]));
+        }
+        dfa
+    }
+
+    fn next(&self, state: usize, byte: u8) -> usize {
+        self.transitions[state][byte as usize]
+    }
+
+    // The state before any input has been read.
+    pub fn start_state(&self) -> StateId {
+        StateId::new(0)
+    }
+
+    pub fn dead_state(&self) -> StateId {
+        StateId::DEAD
+    }
+
+    // Returns the state after reading byte. Stays in the dead state once it has been reached.
+    pub fn next_state(&self, state: StateId, byte: u8) -> StateId {
+        StateId::new(self.transitions.get(state.as_usize()).map_or(DEAD, |row| row[byte as usize]))
+    }
+
+    // Returns true if the bytes read so far are a whole match, which is never the case halfway through a character.
+    pub fn is_match_state(&self, state: StateId) -> bool {
+        self.terminal.get(state.as_usize()).is_some_and(|terminal| *terminal)
+    }
+
+    pub fn is_dead_state(&self, state: StateId) -> bool {
+        state == StateId::DEAD
+    }
+
+    // Returns the state after the end of the input: the same state if the input matches, and the dead state otherwise.
+    pub fn eoi_transition(&self, state: StateId) -> StateId {
+        if self.is_match_state(state) { state } else { StateId::DEAD }
+    }
+}
+
+impl DFA {
+    // Compiles the DFA into one that reads the UTF-8 encoding of the input one byte at a time.
+    pub fn to_bytes(&self) -> ByteDFA {
+        ByteDFA::new(&ByteNfa::new(self, false), false)
+    }
+}
+
+// Splits (low, high) ranges of characters into sequences of byte ranges, such that the UTF-8 encodings
+// of the characters are exactly the byte strings matched by one of the sequences.
+// With raw_bytes set (without Unicode), the characters for raw bytes become a single byte.
+fn byte_sequences(chars: &[(char, char)], raw_bytes: bool) -> Vec<Vec<(u8, u8)>> {
+    let mut sequences: Vec<Vec<(u8, u8)>> = Vec::new();
+    for (low, high) in ranges::normalize(chars.to_vec()) {
+        let (low, mut high) = (low as u32, high as u32);
+        if raw_bytes && high >= RAW_BYTES {
+            let raw_low = low.max(RAW_BYTES);
+            let byte = |value: u32| raw_byte(char::from_u32(value).unwrap()).unwrap();
+            sequences.push(vec![(byte(raw_low), byte(high))]);
+            if low == raw_low {
+                continue;
+            }
+            high = RAW_BYTES - 1;
+        }
+        utf8_sequences(low, high, &mut sequences);
+    }
+    sequences
+}
+
+// Adds the byte range sequences for the characters low..=high.
+// The range is split until both ends encode to the same number of bytes and every byte after
+// the first that differs covers its whole range, so the bytes can be paired up.
+fn utf8_sequences(low: u32, high: u32, sequences: &mut Vec<Vec<(u8, u8)>>) {
+    let mut stack: Vec<(u32, u32)> = vec![(low, high)];
+    'ranges: while let Some((low, high)) = stack.pop() {
+        // Surrogates aren't characters
+        if low < 0xD800 && high > 0xDFFF {
+            stack.push((0xE000, high));
+            stack.push((low, 0xD7FF));
+            continue;
+        }
+        // Ends that encode to a different number of bytes
+        for max in [0x7F, 0x7FF, 0xFFFF] {
+            if low <= max && max < high {
+                stack.push((max + 1, high));
+                stack.push((low, max));
+                continue 'ranges;
+            }
+        }
+        if high <= 0x7F {
+            sequences.push(vec![(low as u8, high as u8)]);
+            continue;
+        }
+        for i in 1..4 {
+            let mask: u32 = (1 << (6 * i)) - 1;
+            if low & !mask != high & !mask {
+                if low & mask != 0 {
+                    stack.push(((low | mask) + 1, high));
+                    stack.push((low, low | mask));
+                    continue 'ranges;
+                }
+                if high & mask != mask {
+                    stack.push((high & !mask, high));
+                    stack.push((low, (high & !mask) - 1));
+                    continue 'ranges;
+                }
+            }
+        }
+        let (mut low_bytes, mut high_bytes) = ([0u8; 4], [0u8; 4]);
+        let low_bytes = char::from_u32(low).unwrap().encode_utf8(&mut low_bytes).as_bytes();
+        let high_bytes = char::from_u32(high).unwrap().encode_utf8(&mut high_bytes).as_bytes();
+        sequences.push(low_bytes.iter().zip(high_bytes).map(|(low, high)| (*low, *high)).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steps::{step1, step2, step3, step4};
+
+    fn found(regex: &Regex, haystack: &[u8]) -> Vec<(usize, usize)> {
+        regex.find_iter(haystack).map(|found| (found.start, found.end)).collect()
+    }
+
+    #[test]
+    fn test_bytes_find() {
+        let regex = Regex::new(r"/\d+/");
+        assert_eq!(found(&regex, b"a1\xff23\x00456"), vec![(1, 2), (3, 5), (6, 9)]);
+        assert_eq!(regex.find(b"\xfe\xff99").unwrap().as_bytes(), b"99");
+        assert!(regex.matches(b"2024"));
+        assert!(!regex.matches(b"20\xff24"));
+        assert!(!regex.is_match(b"\x80\x81"));
+        assert_eq!(Regex::try_new("/[z-a]/").err(), Some(Error::syntax("invalid character class")));
+        assert!(Regex::try_without_unicode("/./").unwrap().matches(b"\xff"));
+    }
+
+    #[test]
+    fn test_bytes_utf8() {
+        let regex = Regex::new("/café|ü+/");
+        let haystack = "un café, üü".as_bytes();
+        assert_eq!(found(&regex, haystack), vec![(3, 8), (10, 14)]);
+        // the bytes of é on their own are not a match
+        assert!(!regex.is_match(b"caf\xc3"));
+        // empty matches are never inside a character
+        assert_eq!(found(&Regex::new("/x*/"), "é".as_bytes()), vec![(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn test_bytes_dfa() {
+        let dfa = step4(step3(step2(step1("/é/".to_string()))));
+        let bytes = dfa.to_bytes();
+        let run = |input: &[u8]| input.iter().fold(bytes.start_state(), |state, byte| bytes.next_state(state, *byte));
+        assert!(bytes.is_match_state(run(&[0xC3, 0xA9])));
+        // halfway through é
+        let half = run(&[0xC3]);
+        assert!(!bytes.is_match_state(half) && !bytes.is_dead_state(half));
+        // é in Latin-1 isn't UTF-8
+        assert!(bytes.is_dead_state(run(&[0xE9])));
+        assert!(bytes.is_dead_state(bytes.next_state(bytes.dead_state(), 0xC3)));
+        assert_eq!(bytes.eoi_transition(half), bytes.dead_state());
+    }
+
+    #[test]
+    fn test_bytes_without_unicode() {
+        let unicode = Regex::new("/a.b/");
+        let bytes = Regex::without_unicode("/a.b/");
+        assert!(!unicode.is_match(b"a\xffb"));
+        assert!(bytes.is_match(b"a\xffb"));
+        assert!(bytes.is_match(b"a\x00b"));
+        assert!(!bytes.is_match(b"a\nb"));
+        assert!(Regex::without_unicode("/a.b/s").is_match(b"a\nb"));
+        assert_eq!(found(&Regex::without_unicode(r"/[^a]\W/"), b"a\x80\x81"), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn test_utf8_sequences() {
+        let mut sequences: Vec<Vec<(u8, u8)>> = Vec::new();
+        utf8_sequences(0x0, 0x10FFFF, &mut sequences);
+        assert_eq!(sequences, vec![
+            vec![(0x00, 0x7F)],
+            vec![(0xC2, 0xDF), (0x80, 0xBF)],
+            vec![(0xE0, 0xE0), (0xA0, 0xBF), (0x80, 0xBF)],
+            vec![(0xE1, 0xEC), (0x80, 0xBF), (0x80, 0xBF)],
+            vec![(0xED, 0xED), (0x80, 0x9F), (0x80, 0xBF)],
+            vec![(0xEE, 0xEF), (0x80, 0xBF), (0x80, 0xBF)],
+            vec![(0xF0, 0xF0), (0x90, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+            vec![(0xF1, 0xF3), (0x80, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+            vec![(0xF4, 0xF4), (0x80, 0x8F), (0x80, 0xBF), (0x80, 0xBF)],
+        ]);
+    }
+}
@@ -0,0 +1,214 @@
+use automata::NFA;
+use regex::Match;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Index;
+use std::sync::Arc;
+
+// Where each capture group of a regex matched.
+// Group 0 is always the whole match, the other groups are numbered by their opening parenthesis.
+#[derive(Clone, Debug)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    names: Arc<HashMap<String, usize>>,
+}
+
+impl<'h> Captures<'h> {
+    pub(crate) fn new(haystack: &'h str, slots: Vec<Option<usize>>, names: Arc<HashMap<String, usize>>) -> Self {
+        Captures { haystack, slots, names }
+    }
+
+    // Returns the match for group i, or None if the group did not take part in the match.
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        match (self.slots.get(i * 2), self.slots.get(i * 2 + 1)) {
+            (Some(&Some(start)), Some(&Some(end))) => Some(Match::new(self.haystack, start, end)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        self.get(*self.names.get(name)?)
+    }
+
+    // Number of groups, including group 0.
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    // Appends the template to dst, with every reference to a group replaced by what it matched.
+    // $1 or ${1} refers to a group by number and $name or ${name} by name. $$ is a literal $.
+    // $name uses the longest run of letters, digits and underscores, so write ${1}a rather than $1a.
+    // Groups that don't exist or didn't match are replaced by nothing.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            dst.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+            if rest.starts_with('$') {
+                dst.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+            let (reference, after) = if rest.starts_with('{') {
+                match rest.find('}') {
+                    Some(close) => (&rest[1..close], &rest[close + 1..]),
+                    None => ("", rest),
+                }
+            } else {
+                let length = rest.find(|c: char| c != '_' && !c.is_alphanumeric()).unwrap_or(rest.len());
+                (&rest[..length], &rest[length..])
+            };
+            if reference.is_empty() {
+                // Not a reference after all, so the $ stays as it is
+                dst.push('$');
+                continue;
+            }
+            let found = match reference.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(reference),
+            };
+            if let Some(found) = found {
+                dst.push_str(found.as_str());
+            }
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+// caps[i] and caps["name"] give the text of a group, and panic if the group didn't match.
+impl<'h> Index<usize> for Captures<'h> {
+    type Output = str;
+
+    fn index(&self, i: usize) -> &str {
+        self.get(i).map(|found| found.as_str()).unwrap_or_else(|| panic!("no group at index {}", i))
+    }
+}
+
+impl<'h, 'n> Index<&'n str> for Captures<'h> {
+    type Output = str;
+
+    fn index(&self, name: &'n str) -> &str {
+        self.name(name).map(|found| found.as_str()).unwrap_or_else(|| panic!("no group named {}", name))
+    }
+}
+
+// Finds where each capture group matched, given that the whole regex matches haystack[start..end].
+//
+// The ε-NFA from step1 is simulated with one thread per node. Threads are kept in the order a
+// backtracking matcher would try them, so each group reports the same text that such a matcher would.
+pub(crate) fn capture_slots(nfa: &NFA, haystack: &str, start: usize, end: usize) -> Vec<Option<usize>> {
+    let mut slots: Vec<Option<usize>> = vec![None; nfa.capture_names.len() * 2];
+    slots[0] = Some(start);
+    slots[1] = Some(end);
+
+    let mut threads: Vec<(usize, Vec<Option<usize>>)> = Vec::new();
+    add_thread(nfa, &mut threads, &mut HashSet::new(), 0, slots, start);
+    for (idx, ch) in haystack[start..end].char_indices() {
+        let pos = start + idx + ch.len_utf8();
+        let mut next_threads: Vec<(usize, Vec<Option<usize>>)> = Vec::new();
+        let mut visited: HashSet<usize> = HashSet::new();
+        for (id, slots) in &threads {
+            for (low, high, to) in &nfa.data[id].paths {
+                if (*low..=*high).contains(&ch) {
+                    add_thread(nfa, &mut next_threads, &mut visited, *to, slots.clone(), pos);
+                }
+            }
+        }
+        threads = next_threads;
+    }
+    threads.into_iter()
+        .find(|(id, _)| nfa.data[id].is_terminal)
+        .map(|(_, slots)| slots)
+        .expect("capture_slots was given a span that doesn't match")
+}
+
+// Follows the ε connections from id, recording captures on the way, and adds a thread for every
+// node that reads a character or is terminal. Nodes that already have a thread are skipped,
+// because the earlier thread has the higher priority.
+fn add_thread(nfa: &NFA, threads: &mut Vec<(usize, Vec<Option<usize>>)>, visited: &mut HashSet<usize>,
+              id: usize, slots: Vec<Option<usize>>, pos: usize) {
+    let mut stack: Vec<(usize, Vec<Option<usize>>)> = vec![(id, slots)];
+    while let Some((id, mut slots)) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let node = &nfa.data[&id];
+        if let Some(slot) = node.capture {
+            slots[slot] = Some(pos);
+        }
+        if node.is_terminal || !node.paths.is_empty() {
+            threads.push((id, slots.clone()));
+        }
+        for to in node.epsilons.iter().rev() {
+            if !visited.contains(to) {
+                stack.push((*to, slots.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    fn groups(regex: &str, haystack: &str) -> Vec<Option<String>> {
+        let caps = Regex::new(regex).captures(haystack).unwrap();
+        (0..caps.len()).map(|i| caps.get(i).map(|m| m.as_str().to_string())).collect()
+    }
+
+    fn some(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn test_captures_basic() {
+        assert_eq!(groups(r"/(\d+)-(\d+)/", "tel 12-345"), vec![some("12-345"), some("12"), some("345")]);
+        assert_eq!(groups("/(a)|(b)/", "b"), vec![some("b"), None, some("b")]);
+    }
+
+    #[test]
+    fn test_captures_nested_and_repeated() {
+        assert_eq!(groups("/((a)b)+/", "abab"), vec![some("abab"), some("ab"), some("a")]);
+        assert_eq!(groups("/(?:x(y))*/", "xyxy"), vec![some("xyxy"), some("y")]);
+        assert_eq!(groups("/(a){2}(b)?/", "aa"), vec![some("aa"), some("a"), None]);
+    }
+
+    #[test]
+    fn test_captures_priority() {
+        // greedy groups take as much as they can, lazy ones as little
+        assert_eq!(groups("/(a*)(a*)/", "aaa"), vec![some("aaa"), some("aaa"), some("")]);
+        assert_eq!(groups("/(a*?)(a*)/", "aaa"), vec![some("aaa"), some(""), some("aaa")]);
+    }
+
+    #[test]
+    fn test_captures_names() {
+        let regex = Regex::new(r"/(?<year>\d{4})-(?P<month>\d\d)/");
+        let caps = regex.captures("on 2024-05").unwrap();
+        assert_eq!(&caps["year"], "2024");
+        assert_eq!(&caps[2], "05");
+        assert!(caps.name("day").is_none());
+        assert_eq!(regex.capture_names(), vec![None, Some("year"), Some("month")]);
+    }
+
+    #[test]
+    fn test_expand() {
+        let regex = Regex::new(r"/(?<first>\w+) (\w+)/");
+        let caps = regex.captures("hello world").unwrap();
+        let expand = |template: &str| {
+            let mut dst = String::new();
+            caps.expand(template, &mut dst);
+            dst
+        };
+        assert_eq!(expand("$2 $first"), "world hello");
+        assert_eq!(expand("${2}_${1}x"), "world_hellox");
+        assert_eq!(expand("$$1 costs $"), "$1 costs $");
+        assert_eq!(expand("$2x$9$missing|"), "|");
+        assert_eq!(expand("${oops"), "${oops");
+    }
+}
@@ -0,0 +1,181 @@
+use automata::StateId;
+use automata::DFA;
+use ranges;
+use std::collections::HashMap;
+
+// A DFA compiled into one flat transition table, for matching without any hashing.
+//
+// Characters that every node treats the same way share an equivalence class, so the table only needs
+// a column per class. Class 0 holds the characters that no node has a connection for. The row of a state starts at
+// state * classes, and the last row is the dead state, which all missing connections lead to.
+pub struct DenseDFA {
+    transitions: Vec<StateId>,
+    terminal: Vec<bool>,
+    classes: usize,
+    // Class of each ASCII character
+    ascii: [usize; 128],
+    // Classes of the other characters, as sorted (low, high, class) ranges. Characters that aren't
+    // in any of them are in class 0.
+    ranges: Vec<(char, char, usize)>,
+}
+
+impl DFA {
+    // Compiles the DFA into dense tables, which match the same strings.
+    pub fn to_dense(&self) -> DenseDFA {
+        DenseDFA::new(self, false)
+    }
+}
+
+impl DenseDFA {
+    // @param unanchored sends every missing connection back to the front node instead of the dead state,
+    // which is how the unanchored DFAs of Regex skip over characters that can't be part of a match.
+    pub(crate) fn new(dfa: &DFA, unanchored: bool) -> Self {
+        let mut ids: Vec<usize> = dfa.data.keys().copied().collect();
+        ids.sort();
+        let row: HashMap<usize, usize> = ids.iter().enumerate().map(|(row, id)| (*id, row)).collect();
+        let dead = ids.len();
+        let missing = if unanchored { 0 } else { dead };
+
+        // Characters are in the same class if they lead to the same node from every node
+        let mut signatures: HashMap<Vec<Option<usize>>, usize> = HashMap::new();
+        let mut class_of: Vec<(char, char, usize)> = Vec::new();
+        for (low, high) in dfa.alphabet() {
+            let signature: Vec<Option<usize>> = ids.iter().map(|id| dfa.data[id].follow(&low)).collect();
+            if signature.iter().all(|to| to.is_none()) {
+                continue;
+            }
+            let next_class = signatures.len() + 1;
+            class_of.push((low, high, *signatures.entry(signature).or_insert(next_class)));
+        }
+        let classes = signatures.len() + 1;
+
+        let mut transitions: Vec<StateId> = vec![StateId::new(missing); (ids.len() + 1) * classes];
+        transitions[dead * classes..].iter_mut().for_each(|state| *state = StateId::new(dead));
+        let mut ascii = [0; 128];
+        let mut ranges: Vec<(char, char, usize)> = Vec::new();
+        for (low, high, class) in class_of {
+            for id in &ids {
+                let to = dfa.data[id].follow(&low).map_or(missing, |to| row[&to]);
+                transitions[row[id] * classes + class] = StateId::new(to);
+            }
+            for ch in low..=high.min('\u{7f}') {
+                ascii[ch as usize] = class;
+            }
+            if high.is_ascii() {
+                continue;
+            }
+            let low = low.max('\u{80}');
+            match ranges.last_mut() {
+                Some((_, last_high, last)) if ranges::next_char(*last_high) == Some(low) && *last == class => *last_high = high,
+                _ => ranges.push((low, high, class)),
+            }
+        }
+        let mut terminal: Vec<bool> = ids.iter().map(|id| dfa.data[id].is_terminal).collect();
+        terminal.push(false);
+        DenseDFA { transitions, terminal, classes, ascii, ranges }
+    }
+
+    // Number of equivalence classes, including the class for characters without any connection.
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+
+    // Number of states, including the dead state.
+    pub fn states(&self) -> usize {
+        self.terminal.len()
+    }
+
+    pub fn start_state(&self) -> StateId {
+        StateId::new(0)
+    }
+
+    pub fn dead_state(&self) -> StateId {
+        StateId::new(self.terminal.len() - 1)
+    }
+
+    // Returns the state after reading ch.
+    pub fn next_state(&self, state: StateId, ch: char) -> StateId {
+        self.transitions[self.row(state) * self.classes + self.class(ch)]
+    }
+
+    pub fn is_match_state(&self, state: StateId) -> bool {
+        self.terminal[self.row(state)]
+    }
+
+    pub fn is_dead_state(&self, state: StateId) -> bool {
+        self.row(state) == self.dead_state().as_usize()
+    }
+
+    // Returns the state after the end of the input: the same state if the input matches, and the dead state otherwise.
+    pub fn eoi_transition(&self, state: StateId) -> StateId {
+        if self.is_match_state(state) { state } else { self.dead_state() }
+    }
+
+    // Returns true if the input string matches the DFA.
+    pub fn matches(&self, input: &str) -> bool {
+        let mut state = self.start_state();
+        for ch in input.chars() {
+            state = self.next_state(state, ch);
+            if self.is_dead_state(state) {
+                return false;
+            }
+        }
+        self.is_match_state(state)
+    }
+
+    // The row of a state. The states of a DFA from step4 are its rows, except for DFA::dead_state,
+    // which is past the end of the table like any other id it doesn't have, so they all go to the dead state.
+    fn row(&self, state: StateId) -> usize {
+        state.as_usize().min(self.terminal.len() - 1)
+    }
+
+    fn class(&self, ch: char) -> usize {
+        if ch.is_ascii() {
+            return self.ascii[ch as usize];
+        }
+        ranges::find(&self.ranges, ch).map_or(0, |index| self.ranges[index].2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+
+    #[test]
+    fn test_dense_classes() {
+        let dense = compile("/[a-z]+@[a-z]+/").to_dense();
+        // characters without a connection, the letters, and @
+        assert_eq!(dense.classes(), 3);
+        assert!(dense.matches("me@example"));
+        assert!(!dense.matches("me@"));
+        assert!(!dense.matches("me@exam ple"));
+        assert!(!dense.matches("ME@example"));
+    }
+
+    #[test]
+    fn test_dense_same_as_dfa() {
+        let dfa = compile("/(a|ü)*b[^x]|ж+/");
+        let dense = dfa.to_dense();
+        for input in ["b", "aüb1", "aaby", "abx", "жж", "ж", "üж", "", "ab"] {
+            assert_eq!(dense.matches(input), dfa.matches(input), "{}", input);
+        }
+        let state = dense.next_state(dense.start_state(), 'ж');
+        assert!(dense.is_match_state(state));
+        assert_eq!(dense.eoi_transition(state), state);
+        let dead = dense.next_state(state, 'a');
+        assert!(dense.is_dead_state(dead));
+        assert_eq!(dense.next_state(dead, 'ж'), dead);
+    }
+
+    #[test]
+    fn test_dense_takes_dfa_states() {
+        let dfa = compile("/ab+/");
+        let dense = dfa.to_dense();
+        let state = dfa.next_state(dfa.start_state(), 'a');
+        assert_eq!(dense.next_state(state, 'b'), dfa.next_state(state, 'b'));
+        // the dead state of the DFA isn't a row of the table, but it is dead there too
+        assert!(dense.is_dead_state(dfa.dead_state()));
+        assert!(dense.is_dead_state(dense.next_state(dfa.dead_state(), 'a')));
+        assert!(!dense.is_match_state(dfa.dead_state()));
+    }
+}
@@ -0,0 +1,117 @@
+use automata::DFA;
+use automata::NFA;
+use ranges;
+use std::collections::BTreeMap;
+use table::char_label;
+
+// Graphviz output for the automata, to be drawn with `dot -Tsvg`.
+//
+// Node 0 gets an arrow from a small start point and terminal nodes are drawn with a double circle.
+// All the characters that lead from one node to the same other node share a single edge,
+// labelled with ranges like "a-z, _". ε connections are dashed.
+impl NFA {
+    pub fn to_dot(&self) -> String {
+        let mut ids: Vec<&usize> = self.data.keys().collect();
+        ids.sort();
+        let mut output = dot_header("NFA", &self.regex);
+        for id in ids {
+            let node = &self.data[id];
+            output += &dot_node(*id, node.is_terminal);
+            let mut edges: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+            for (low, high, to) in &node.paths {
+                edges.entry(*to).or_default().push((*low, *high));
+            }
+            for to in &node.epsilons {
+                output += &format!("    {} -> {} [label=\"ε\", style=dashed];\n", id, to);
+            }
+            output += &dot_edges(*id, &edges);
+        }
+        output + "}\n"
+    }
+}
+
+impl DFA {
+    pub fn to_dot(&self) -> String {
+        let mut ids: Vec<&usize> = self.data.keys().collect();
+        ids.sort();
+        let mut output = dot_header("DFA", &self.regex);
+        for id in ids {
+            let node = &self.data[id];
+            output += &dot_node(*id, node.is_terminal);
+            let mut edges: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+            for (low, high, to) in &node.paths {
+                edges.entry(*to).or_default().push((*low, *high));
+            }
+            output += &dot_edges(*id, &edges);
+        }
+        output + "}\n"
+    }
+}
+
+fn dot_header(name: &str, regex: &str) -> String {
+    let mut output = format!("digraph {} {{\n", name);
+    output += "    rankdir=LR;\n";
+    output += &format!("    label=\"{}\";\n", dot_escape(regex));
+    output += "    node [shape=circle];\n";
+    output += "    start [shape=point];\n";
+    output += "    start -> 0;\n";
+    output
+}
+
+fn dot_node(id: usize, is_terminal: bool) -> String {
+    if is_terminal {
+        return format!("    {} [shape=doublecircle];\n", id);
+    }
+    format!("    {};\n", id)
+}
+
+fn dot_edges(from: usize, edges: &BTreeMap<usize, Vec<(char, char)>>) -> String {
+    edges.iter()
+        .map(|(to, chars)| format!("    {} -> {} [label=\"{}\"];\n", from, to, dot_escape(&range_label(chars))))
+        .collect()
+}
+
+// Writes the characters as a list of ranges, like "0-9, _, a-z".
+fn range_label(chars: &[(char, char)]) -> String {
+    let parts: Vec<String> = ranges::normalize(chars.to_vec()).into_iter().map(|(low, high)| {
+        match ranges::range_len(low, high) {
+            1 => char_label(low),
+            2 => format!("{}, {}", char_label(low), char_label(high)),
+            _ => format!("{}-{}", char_label(low), char_label(high)),
+        }
+    }).collect();
+    parts.join(", ")
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+    use steps::step1;
+
+    #[test]
+    fn test_dfa_to_dot() {
+        let dot = compile("/[a-z]x|[0-3 ]/").to_dot();
+        assert!(dot.starts_with("digraph DFA {\n"));
+        assert!(dot.contains("    label=\"/[a-z]x|[0-3 ]/\";\n"));
+        assert!(dot.contains("    start -> 0;\n    0;\n"));
+        assert!(dot.contains("0 -> 1 [label=\"space, 0-3\"];\n"));
+        assert!(dot.contains("0 -> 2 [label=\"a-z\"];\n"));
+        assert!(dot.contains("    1 [shape=doublecircle];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_nfa_to_dot() {
+        let dot = step1(r#"/"\n|b*/"#.to_string()).to_dot();
+        assert!(dot.starts_with("digraph NFA {\n"));
+        assert!(dot.contains(r#"label="/\"\\n|b*/";"#));
+        assert!(dot.contains("[label=\"ε\", style=dashed];\n"));
+        assert!(dot.contains(r#"[label="\\\""];"#));
+        assert!(dot.contains(r#"[label="\\n"];"#));
+        assert!(dot.contains("[label=\"b\"];"));
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt;
+
+// Errors returned by RegexBuilder::build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    // The pattern would go over one of the limits set on the RegexBuilder.
+    TooBig { stage: Stage, limit: usize },
+    // The pattern isn't a valid regex, like "/(a/" or "/a{3,1}/".
+    Syntax { message: String },
+}
+
+impl Error {
+    pub(crate) fn syntax(message: &str) -> Self {
+        Error::Syntax { message: message.to_string() }
+    }
+}
+
+// Which limit a pattern went over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    // Groups nested deeper than nest_limit
+    Nesting,
+    // A counted repetition like {n,m} with a bound above repeat_limit
+    Repetition,
+    // More ε-NFA nodes than size_limit
+    Nfa,
+    // More DFA nodes than dfa_size_limit. RegexBuilder::build uses a LazyDFA instead of returning it.
+    Dfa,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TooBig { stage: Stage::Nesting, limit } => write!(f, "the groups are nested more than {} deep", limit),
+            Error::TooBig { stage: Stage::Repetition, limit } => write!(f, "a repetition goes over the limit of {}", limit),
+            Error::TooBig { stage: Stage::Nfa, limit } => write!(f, "the NFA would have more than {} nodes", limit),
+            Error::TooBig { stage: Stage::Dfa, limit } => write!(f, "the DFA would have more than {} nodes", limit),
+            Error::Syntax { message } => write!(f, "invalid regex: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,365 @@
+use automata::DFA;
+use big_uint::BigUint;
+use random::Rng;
+use ranges;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+// Generating the strings a DFA accepts.
+impl DFA {
+    // Returns every string the DFA accepts, shortest first and then in alphabetical order.
+    // The iterator ends after the longest string of a finite language, and never ends for an
+    // infinite one unless it is given a limit with Strings::max_len.
+    pub fn strings<'d>(&'d self) -> Strings<'d> {
+        // An empty language is finite too, and has no strings of length 0
+        let limit = if self.is_finite() { Some(self.max_len().unwrap_or(0)) } else { None };
+        Strings {
+            dfa: self,
+            finishing: Vec::new(),
+            limit,
+            length: 0,
+            next_length: 0,
+            stack: Vec::new(),
+            prefix: String::new(),
+        }
+    }
+
+    // Returns the number of strings of len characters that the DFA accepts.
+    pub fn count_of_length(&self, len: usize) -> BigUint {
+        self.counts_up_to(len)[len][&0].clone()
+    }
+
+    // Returns a string of len characters that the DFA accepts, where every such string is equally likely,
+    // or None if it accepts none of that length.
+    // Picks a random number below the count, then walks down the DFA choosing the range of characters
+    // whose strings contain that number. Each character of the range leads to the same number of strings,
+    // so the remainder after dividing by the size of the range picks the character.
+    pub fn sample<R: Rng>(&self, len: usize, rng: &mut R) -> Option<String> {
+        let counts = self.counts_up_to(len);
+        if counts[len][&0].is_zero() {
+            return None;
+        }
+        let mut index = counts[len][&0].random_below(rng);
+        let mut id: usize = 0;
+        let mut sample = String::new();
+        for remaining in (0..len).rev() {
+            for (low, high, to) in &self.data[&id].paths {
+                let size = ranges::range_len(*low, *high);
+                let mut count = counts[remaining][to].clone();
+                count *= size;
+                if index < count {
+                    let offset = index.div_rem_small(size);
+                    sample.push(ranges::nth_char(*low, offset));
+                    id = *to;
+                    break;
+                }
+                index -= &count;
+            }
+        }
+        Some(sample)
+    }
+
+    // Returns up to limit strings that the DFA rejects but that are a single change away from one it accepts:
+    // cut short, with a character added at the end, or with one character replaced, removed or inserted.
+    // The accepted strings are taken shortest first, so the near misses are short too.
+    // The characters tried are the first of each range that the nodes treat the same way, and one
+    // character (printable if possible) that no node has a connection for.
+    pub fn near_misses(&self, limit: usize) -> Vec<NearMiss> {
+        let alphabet = self.representatives();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut near_misses: Vec<NearMiss> = Vec::new();
+        for original in self.strings().take(limit) {
+            let chars: Vec<char> = original.chars().collect();
+            let mut candidates: Vec<(Vec<char>, NearMissReason)> = Vec::new();
+            for len in (0..chars.len()).rev() {
+                candidates.push((chars[..len].to_vec(), NearMissReason::Truncated { len }));
+            }
+            for ch in &alphabet {
+                let mut changed = chars.clone();
+                changed.push(*ch);
+                candidates.push((changed, NearMissReason::ExtraChar { ch: *ch }));
+            }
+            for position in 0..chars.len() {
+                for ch in alphabet.iter().filter(|ch| **ch != chars[position]) {
+                    let mut changed = chars.clone();
+                    changed[position] = *ch;
+                    candidates.push((changed, NearMissReason::Substituted { position, ch: *ch }));
+                }
+                let mut changed = chars.clone();
+                changed.remove(position);
+                candidates.push((changed, NearMissReason::Deleted { position }));
+                for ch in &alphabet {
+                    let mut changed = chars.clone();
+                    changed.insert(position, *ch);
+                    candidates.push((changed, NearMissReason::Inserted { position, ch: *ch }));
+                }
+            }
+            for (input, reason) in candidates {
+                let input: String = input.into_iter().collect();
+                if near_misses.len() == limit {
+                    return near_misses;
+                }
+                if !self.matches(&input) && seen.insert(input.clone()) {
+                    near_misses.push(NearMiss { input, original: original.clone(), reason });
+                }
+            }
+        }
+        near_misses
+    }
+
+    fn representatives(&self) -> Vec<char> {
+        let mut alphabet: Vec<char> = Vec::new();
+        let mut unconnected: Option<char> = None;
+        for (low, high) in self.alphabet() {
+            if self.data.values().any(|node| node.follow(&low).is_some()) {
+                alphabet.push(low);
+            } else if low <= '~' && high >= ' ' && unconnected.map_or(true, |ch| !(' '..='~').contains(&ch)) {
+                unconnected = Some(low.max(' '));
+            } else if unconnected.is_none() {
+                unconnected = Some(low);
+            }
+        }
+        alphabet.extend(unconnected);
+        alphabet.sort();
+        alphabet
+    }
+
+    // counts[k][id] is the number of strings of k characters that node id leads to a terminal node with.
+    fn counts_up_to(&self, len: usize) -> Vec<HashMap<usize, BigUint>> {
+        let mut counts: Vec<HashMap<usize, BigUint>> = vec![self.data.values()
+            .map(|node| (node.id, BigUint::from(node.is_terminal as u64)))
+            .collect()];
+        for k in 0..len {
+            let next: HashMap<usize, BigUint> = self.data.values().map(|node| {
+                let mut count = BigUint::zero();
+                for (low, high, to) in &node.paths {
+                    let mut paths = counts[k][to].clone();
+                    paths *= ranges::range_len(*low, *high);
+                    count += &paths;
+                }
+                (node.id, count)
+            }).collect();
+            counts.push(next);
+        }
+        counts
+    }
+}
+
+// Iterator returned by DFA::strings.
+//
+// Each length is handled by a depth first search that tries the characters in order. It only enters
+// nodes that can still reach a terminal node with exactly the characters that are left, so every
+// branch it takes ends in a string, and a range that leads to any other node is skipped as a whole.
+pub struct Strings<'d> {
+    dfa: &'d DFA,
+    // finishing[k] holds the nodes that accept some string of exactly k characters
+    finishing: Vec<HashSet<usize>>,
+    limit: Option<usize>,
+    length: usize,
+    next_length: usize,
+    // The nodes on the current path, each with the next character to try from it (None when all were tried)
+    stack: Vec<(usize, Option<char>)>,
+    prefix: String,
+}
+
+impl<'d> Strings<'d> {
+    // Stops after the strings of len characters.
+    pub fn max_len(mut self, len: usize) -> Self {
+        self.limit = Some(self.limit.map_or(len, |limit| limit.min(len)));
+        self
+    }
+
+    fn can_finish(&mut self, id: usize, remaining: usize) -> bool {
+        while self.finishing.len() <= remaining {
+            let nodes: HashSet<usize> = match self.finishing.last() {
+                None => self.dfa.data.values().filter(|node| node.is_terminal).map(|node| node.id).collect(),
+                Some(after) => self.dfa.data.values()
+                    .filter(|node| node.paths.iter().any(|(_, _, to)| after.contains(to)))
+                    .map(|node| node.id)
+                    .collect(),
+            };
+            self.finishing.push(nodes);
+        }
+        self.finishing[remaining].contains(&id)
+    }
+}
+
+impl<'d> Iterator for Strings<'d> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let (id, cursor) = match self.stack.last() {
+                Some(top) => *top,
+                None => {
+                    // Done with this length, so start the search for the next one
+                    if self.limit.is_some_and(|limit| self.next_length > limit) {
+                        return None;
+                    }
+                    self.length = self.next_length;
+                    self.next_length += 1;
+                    if self.can_finish(0, self.length) {
+                        self.stack.push((0, Some('\0')));
+                    }
+                    continue;
+                }
+            };
+            let depth = self.stack.len() - 1;
+            if depth == self.length {
+                self.stack.pop();
+                let found = self.prefix.clone();
+                self.prefix.pop();
+                return Some(found);
+            }
+            let mut next: Option<(char, usize)> = None;
+            if let Some(cursor) = cursor {
+                let remaining = self.length - depth - 1;
+                let dfa = self.dfa;
+                for (low, _, to) in dfa.data[&id].paths.iter().filter(|(_, high, _)| *high >= cursor) {
+                    if self.can_finish(*to, remaining) {
+                        next = Some(((*low).max(cursor), *to));
+                        break;
+                    }
+                }
+            }
+            match next {
+                Some((ch, to)) => {
+                    self.stack.last_mut().unwrap().1 = ranges::next_char(ch);
+                    self.prefix.push(ch);
+                    self.stack.push((to, Some('\0')));
+                }
+                None => {
+                    self.stack.pop();
+                    self.prefix.pop();
+                }
+            }
+        }
+    }
+}
+
+// A string the DFA rejects, made by changing one that it accepts. Returned by DFA::near_misses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NearMiss {
+    pub input: String,
+    // The accepted string it was made from
+    pub original: String,
+    pub reason: NearMissReason,
+}
+
+// How a near miss was made from the accepted string. Positions count characters, not bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NearMissReason {
+    // Only the first len characters were kept
+    Truncated { len: usize },
+    // ch was added at the end
+    ExtraChar { ch: char },
+    // The character at position was replaced by ch
+    Substituted { position: usize, ch: char },
+    // The character at position was removed
+    Deleted { position: usize },
+    // ch was inserted before position
+    Inserted { position: usize, ch: char },
+}
+
+impl fmt::Display for NearMiss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} is rejected: it is {:?} ", self.input, self.original)?;
+        match self.reason {
+            NearMissReason::Truncated { len } => write!(f, "cut after {} characters", len),
+            NearMissReason::ExtraChar { ch } => write!(f, "with an extra {:?} at the end", ch),
+            NearMissReason::Substituted { position, ch } => write!(f, "with {:?} at position {}", ch, position),
+            NearMissReason::Deleted { position } => write!(f, "without the character at position {}", position),
+            NearMissReason::Inserted { position, ch } => write!(f, "with {:?} inserted at position {}", ch, position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::XorShift;
+    use std::collections::HashMap;
+    use steps::compile;
+
+    fn strings(regex: &str, max_len: usize) -> Vec<String> {
+        compile(regex).strings().max_len(max_len).collect()
+    }
+
+    #[test]
+    fn test_strings_shortlex() {
+        assert_eq!(strings("/(a|b)*/", 2), vec!["", "a", "b", "aa", "ab", "ba", "bb"]);
+        assert_eq!(strings("/b|ab*/", 3), vec!["a", "b", "ab", "abb"]);
+        assert_eq!(strings("/(aa)+/", 5), vec!["aa", "aaaa"]);
+    }
+
+    #[test]
+    fn test_strings_finite_language_ends() {
+        let all: Vec<String> = compile("/[ab]c?|abc/").strings().collect();
+        assert_eq!(all, vec!["a", "b", "ac", "bc", "abc"]);
+        assert_eq!(compile(r"/a[^\s\S]/").strings().count(), 0);
+        assert_eq!(compile("//").strings().collect::<Vec<String>>(), vec![""]);
+    }
+
+    #[test]
+    fn test_strings_infinite_language() {
+        let first: Vec<String> = compile(r"/\d+/").strings().take(12).collect();
+        assert_eq!(first[..3], ["0", "1", "2"]);
+        assert_eq!(first[10..], ["00", "01"]);
+    }
+
+    #[test]
+    fn test_count_of_length() {
+        let dfa = compile("/[ab]*c/");
+        assert_eq!(dfa.count_of_length(0).to_u64(), Some(0));
+        assert_eq!(dfa.count_of_length(1).to_u64(), Some(1));
+        assert_eq!(dfa.count_of_length(4).to_u64(), Some(8));
+        // 2^100 strings, which doesn't fit in a u64
+        assert_eq!(compile("/[01]*/").count_of_length(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!(compile("/a|bc|de/").count_of_length(2).to_u64(), Some(2));
+    }
+
+    #[test]
+    fn test_sample() {
+        let dfa = compile("/a[bc]d?|e/");
+        let mut rng = XorShift::new(42);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for _ in 0..400 {
+            let sample = dfa.sample(3, &mut rng).unwrap();
+            assert!(dfa.matches(&sample));
+            *seen.entry(sample).or_insert(0) += 1;
+        }
+        // both strings of length 3 come up about equally often
+        assert_eq!(seen.len(), 2);
+        assert!(seen.values().all(|count| *count > 150));
+        assert_eq!(dfa.sample(4, &mut rng), None);
+        assert_eq!(compile("//").sample(0, &mut rng), Some(String::new()));
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let dfa = compile(r"/\d{2}-[ab]/");
+        let near_misses = dfa.near_misses(100);
+        assert_eq!(near_misses.len(), 100);
+        for near_miss in &near_misses {
+            assert!(!dfa.matches(&near_miss.input));
+            assert!(dfa.matches(&near_miss.original));
+        }
+        assert_eq!(near_misses[0], NearMiss {
+            input: "00-".to_string(),
+            original: "00-a".to_string(),
+            reason: NearMissReason::Truncated { len: 3 },
+        });
+        assert_eq!(near_misses[0].to_string(), "\"00-\" is rejected: it is \"00-a\" cut after 3 characters");
+        assert!(near_misses.iter().any(|near_miss| near_miss.input == "00-a-"));
+        assert!(near_misses.iter().any(|near_miss| near_miss.reason == NearMissReason::Deleted { position: 2 }));
+    }
+
+    #[test]
+    fn test_near_misses_of_universal_language() {
+        assert_eq!(compile(r"/[\s\S]*/").near_misses(10), vec![]);
+        // every other character is rejected now, not only the ones the regex mentions
+        let near_misses = compile("/[ab]*/").near_misses(3);
+        assert_eq!(near_misses[0].input, " ");
+    }
+}
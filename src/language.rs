@@ -0,0 +1,321 @@
+use automata::DFA;
+use ranges;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+
+// Which of the two DFAs compared by DFA::equivalent accepts a counterexample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// A string that one DFA accepts and the other rejects, returned by DFA::equivalent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Counterexample {
+    pub input: String,
+    pub accepted_by: Side,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (accepts, rejects) = match self.accepted_by {
+            Side::Left => ("left", "right"),
+            Side::Right => ("right", "left"),
+        };
+        write!(f, "{:?} is accepted by the {} DFA but not the {} one", self.input, accepts, rejects)
+    }
+}
+
+impl DFA {
+    // Checks that both DFAs accept exactly the same strings.
+    // Otherwise returns the shortest string (the first in alphabetical order if there are several)
+    // that only one of them accepts, where self is the left side.
+    // Both DFAs are run side by side, and a breadth first search looks for a pair of nodes
+    // where exactly one of them is terminal.
+    pub fn equivalent(&self, other: &DFA) -> Result<(), Counterexample> {
+        // The strings accepted by exactly one side
+        let differences = self.product(other, "^", |a, b| a != b);
+        match differences.shortest_match() {
+            None => Ok(()),
+            Some(input) => {
+                let accepted_by = if self.matches(&input) { Side::Left } else { Side::Right };
+                Err(Counterexample { input, accepted_by })
+            }
+        }
+    }
+
+    // Returns true if the DFA doesn't accept any string. shortest_match gives a witness otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.shortest_match().is_none()
+    }
+
+    // Returns true if the DFA accepts every string. shortest_non_match gives a witness otherwise.
+    pub fn is_universal(&self) -> bool {
+        self.shortest_non_match().is_none()
+    }
+
+    // Returns true if every string this DFA accepts is also accepted by the other one.
+    // subset_counterexample gives a witness otherwise.
+    pub fn is_subset_of(&self, other: &DFA) -> bool {
+        self.subset_counterexample(other).is_none()
+    }
+
+    // Returns true if the DFA accepts a limited number of strings, which means no loop
+    // in the DFA can lead to a terminal node. longest_match gives the longest of them.
+    pub fn is_finite(&self) -> bool {
+        let useful = self.useful_nodes();
+        // 0 = not visited yet, 1 = on the current path, 2 = done
+        let mut state: HashMap<usize, u8> = HashMap::new();
+        for start in &useful {
+            if state.contains_key(start) {
+                continue;
+            }
+            let mut stack: Vec<(usize, bool)> = vec![(*start, false)];
+            while let Some((id, finished)) = stack.pop() {
+                if finished {
+                    state.insert(id, 2);
+                    continue;
+                }
+                match state.get(&id) {
+                    Some(1) => return false,
+                    Some(2) => continue,
+                    _ => {}
+                }
+                state.insert(id, 1);
+                stack.push((id, true));
+                for (_, _, to) in &self.data[&id].paths {
+                    if !useful.contains(to) {
+                        continue;
+                    }
+                    if state.get(to) == Some(&1) {
+                        return false;
+                    }
+                    if !state.contains_key(to) {
+                        stack.push((*to, false));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Length (in characters) of the shortest string the DFA accepts, or None if it accepts nothing.
+    pub fn min_len(&self) -> Option<usize> {
+        self.shortest_match().map(|input| input.chars().count())
+    }
+
+    // Length (in characters) of the longest string the DFA accepts,
+    // or None if there is no limit (or it accepts nothing).
+    pub fn max_len(&self) -> Option<usize> {
+        self.longest_match().map(|input| input.chars().count())
+    }
+
+    // Returns a string that this DFA accepts and the other doesn't, the shortest one there is.
+    pub fn subset_counterexample(&self, other: &DFA) -> Option<String> {
+        self.difference(other).shortest_match()
+    }
+
+    // Returns the shortest string the DFA rejects, or None if it accepts everything.
+    // Each range of characters is represented by its first character.
+    pub fn shortest_non_match(&self) -> Option<String> {
+        let mut paths: HashMap<usize, String> = HashMap::from([(0, String::new())]);
+        let mut level: Vec<usize> = vec![0];
+        // A missing connection rejects a string one character longer than the node's own,
+        // so it is only used if no node on the next level is rejecting.
+        let mut missing: Option<String> = None;
+        while !level.is_empty() {
+            if let Some(id) = level.iter().find(|id| !self.data[id].is_terminal) {
+                return Some(paths[id].clone());
+            }
+            if missing.is_some() {
+                return missing;
+            }
+            let mut next_level: Vec<usize> = Vec::new();
+            for id in &level {
+                let node = &self.data[id];
+                let path = paths[id].clone();
+                if missing.is_none() {
+                    let connected: Vec<(char, char)> = node.paths.iter().map(|(low, high, _)| (*low, *high)).collect();
+                    let first_missing = ranges::complement(&connected).first().map(|(low, _)| *low);
+                    missing = first_missing.map(|ch| format!("{}{}", path, ch));
+                }
+                for (low, _, to) in &node.paths {
+                    if !paths.contains_key(to) {
+                        paths.insert(*to, format!("{}{}", path, low));
+                        next_level.push(*to);
+                    }
+                }
+            }
+            level = next_level;
+        }
+        missing
+    }
+
+    // Returns the longest string the DFA accepts (the first in alphabetical order if there are several),
+    // or None if it accepts arbitrarily long strings or nothing at all.
+    pub fn longest_match(&self) -> Option<String> {
+        if !self.is_finite() || self.is_empty() {
+            return None;
+        }
+        let useful = self.useful_nodes();
+        // The longest string that leads from each node to a terminal node. Without loops, a node
+        // can be worked out once everything it connects to has been.
+        let mut longest: HashMap<usize, String> = HashMap::new();
+        let mut stack: Vec<(usize, bool)> = vec![(0, false)];
+        while let Some((id, ready)) = stack.pop() {
+            if longest.contains_key(&id) {
+                continue;
+            }
+            let node = &self.data[&id];
+            let next: Vec<(char, usize)> = node.paths.iter()
+                .filter(|(_, _, to)| useful.contains(to))
+                .map(|(low, _, to)| (*low, *to))
+                .collect();
+            if !ready {
+                stack.push((id, true));
+                stack.extend(next.iter().map(|(_, to)| (*to, false)));
+                continue;
+            }
+            let mut best: Option<String> = if node.is_terminal { Some(String::new()) } else { None };
+            for (ch, to) in next {
+                let candidate = format!("{}{}", ch, longest[&to]);
+                if best.as_ref().map_or(true, |best| candidate.chars().count() > best.chars().count()) {
+                    best = Some(candidate);
+                }
+            }
+            longest.insert(id, best.unwrap());
+        }
+        longest.remove(&0)
+    }
+
+    // Returns the shortest string the DFA accepts, trying characters in order,
+    // or None if it doesn't accept anything.
+    pub fn shortest_match(&self) -> Option<String> {
+        let mut parents: HashMap<usize, (usize, char)> = HashMap::new();
+        let mut queue: VecDeque<usize> = VecDeque::from([0]);
+        while let Some(id) = queue.pop_front() {
+            if self.data[&id].is_terminal {
+                let mut input: Vec<char> = Vec::new();
+                let mut cur = id;
+                while let Some((parent, ch)) = parents.get(&cur) {
+                    input.push(*ch);
+                    cur = *parent;
+                }
+                return Some(input.into_iter().rev().collect());
+            }
+            for (low, _, to) in &self.data[&id].paths {
+                if *to != 0 && !parents.contains_key(to) {
+                    parents.insert(*to, (id, *low));
+                    queue.push_back(*to);
+                }
+            }
+        }
+        None
+    }
+
+    // Nodes that can be reached from the front and can still reach a terminal node.
+    fn useful_nodes(&self) -> HashSet<usize> {
+        let mut reachable: HashSet<usize> = HashSet::from([0]);
+        let mut stack: Vec<usize> = vec![0];
+        while let Some(id) = stack.pop() {
+            for (_, _, to) in &self.data[&id].paths {
+                if reachable.insert(*to) {
+                    stack.push(*to);
+                }
+            }
+        }
+        let mut useful: HashSet<usize> = reachable.iter().copied().filter(|id| self.data[id].is_terminal).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in &reachable {
+                if !useful.contains(id) && self.data[id].paths.iter().any(|(_, _, to)| useful.contains(to)) {
+                    useful.insert(*id);
+                    changed = true;
+                }
+            }
+        }
+        useful
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steps::compile;
+
+    #[test]
+    fn test_equivalent() {
+        assert_eq!(compile("/(a|b)*/").equivalent(&compile("/(a*b*)*/")), Ok(()));
+        assert_eq!(compile("/a(ba)*/").equivalent(&compile("/(ab)*a/")), Ok(()));
+        assert_eq!(compile(r"/\d{2,3}/").equivalent(&compile("/[0-9][0-9][0-9]?/")), Ok(()));
+    }
+
+    #[test]
+    fn test_counterexample_is_shortest() {
+        let result = compile("/a*/").equivalent(&compile("/a+/"));
+        assert_eq!(result, Err(Counterexample { input: String::new(), accepted_by: Side::Left }));
+
+        let result = compile("/[a-c]{2}/").equivalent(&compile("/[a-c]{2}|cab/"));
+        assert_eq!(result, Err(Counterexample { input: "cab".to_string(), accepted_by: Side::Right }));
+
+        let result = compile("/(ab|ba)+/").equivalent(&compile("/(ab|bb)+/"));
+        assert_eq!(result.unwrap_err().input, "ba");
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(compile(r"/\d+/").intersect(&compile("/[a-z]+/")).is_empty());
+        assert!(!compile("/a|b/").is_empty());
+        assert_eq!(compile("/x(yz)+|xy/").shortest_match(), Some("xy".to_string()));
+        assert_eq!(compile("/a*/").shortest_match(), Some(String::new()));
+    }
+
+    #[test]
+    fn test_is_universal() {
+        assert!(!compile("/[ab]*/").is_universal());
+        assert_eq!(compile("/[ab]*/").shortest_non_match(), Some("\0".to_string()));
+        assert!(compile(r"/[\s\S]*/").is_universal());
+        assert!(compile("/(.|\n)*/").is_universal());
+        assert!(!compile("/a/").complement().is_universal());
+        assert!(compile("/a/").union(&compile("/a/").complement()).is_universal());
+        assert_eq!(compile("/a+/").shortest_non_match(), Some(String::new()));
+        // a rejecting node is preferred over a missing connection on the same level
+        assert_eq!(compile("/|[^a]|a[ab]/").shortest_non_match(), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_is_subset_of() {
+        assert!(compile("/ab+/").is_subset_of(&compile("/[a-c]+/")));
+        assert!(!compile("/[a-c]+/").is_subset_of(&compile("/ab+/")));
+        assert_eq!(compile("/[a-c]+/").subset_counterexample(&compile("/ab+/")), Some("a".to_string()));
+        assert!(compile("/x/").intersect(&compile("/y/")).is_subset_of(&compile("/z/")));
+    }
+
+    #[test]
+    fn test_is_finite_and_lengths() {
+        let dfa = compile("/ab?c{1,3}|d/");
+        assert!(dfa.is_finite());
+        assert_eq!(dfa.min_len(), Some(1));
+        assert_eq!(dfa.max_len(), Some(5));
+        assert_eq!(dfa.longest_match(), Some("abccc".to_string()));
+
+        let dfa = compile("/ab*c/");
+        assert!(!dfa.is_finite());
+        assert_eq!(dfa.min_len(), Some(2));
+        assert_eq!(dfa.max_len(), None);
+
+        let empty = compile("/a/").intersect(&compile("/b/"));
+        assert!(empty.is_finite());
+        assert_eq!((empty.min_len(), empty.max_len()), (None, None));
+    }
+
+    #[test]
+    fn test_counterexample_display() {
+        let error = compile("/x/").equivalent(&compile("/y/")).unwrap_err();
+        assert_eq!(error.to_string(), "\"x\" is accepted by the left DFA but not the right one");
+    }
+}
@@ -0,0 +1,384 @@
+use automata::NFA;
+use error::Error;
+use leftmost::SearchState;
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use steps::step2;
+use steps::try_step1;
+use steps::Limits;
+use stream::decode_chunk;
+use stream::read_chunks;
+
+// Default size of each cache, in bytes.
+pub(crate) const DEFAULT_CACHE_CAPACITY: usize = 1 << 20;
+// The cache has to be cleared this many times before giving up is considered.
+const MIN_CLEARS: usize = 3;
+// Giving up when fewer characters than this were read per cached state since the last clear,
+// since the DFA states are then hardly reused and building them costs more than simulating the NFA.
+const MIN_CHARS_PER_STATE: usize = 10;
+
+// A DFA that is built while searching, instead of all at once by step3.
+//
+// Each state stands for a leftmost::SearchState of the NFA from step2, and a state's connections are only
+// worked out the first time a character needs them. Patterns like (a|b)*a(a|b){20} have millions of
+// DFA nodes, but a search only ever visits a few of them. The states are kept in a cache with a
+// limited size, which is cleared when it fills up. If it is cleared too often, the rest of the input
+// is read by simulating the NFA directly, one SearchState at a time.
+//
+// Searches are leftmost-longest like Regex: the leftmost states find where the match ends, and the
+// reverse states (of the reversed NFA) read back from there to find where it starts.
+pub struct LazyDFA {
+    nfa: NFA,
+    // The NFA for the reversed regex, from step2
+    reverse_nfa: NFA,
+    // Whole matches for matches
+    anchored: Cache,
+    // Where the leftmost match ends, for is_match and find_at
+    leftmost: Cache,
+    // Where the match starts, reading back from its end
+    reverse: Cache,
+}
+
+struct Cache {
+    capacity: usize,
+    // The state the searches start in
+    front: SearchState,
+    states: Vec<LazyState>,
+    ids: HashMap<SearchState, usize>,
+    // Estimated number of bytes the states use
+    memory: usize,
+    clears: usize,
+    // Characters read since the last clear
+    read: usize,
+    fallbacks: usize,
+}
+
+struct LazyState {
+    state: SearchState,
+    // The connections worked out so far, as sorted, non-overlapping (low, high, to)
+    paths: Vec<(char, char, usize)>,
+}
+
+// Where a search is: a cached state, or the SearchState itself once the search gave up on the cache.
+enum Cursor {
+    Cached(usize),
+    Simulated(SearchState),
+}
+
+// Rough number of bytes a cached connection and a state (with a copy of its nodes as the key) use.
+const PATH_SIZE: usize = std::mem::size_of::<(char, char, usize)>();
+
+fn state_size(nodes: usize) -> usize {
+    std::mem::size_of::<LazyState>() + 2 * nodes * std::mem::size_of::<usize>() + 32
+}
+
+impl LazyDFA {
+    // @param regex looks like: "/[a-z]+/i", the same as for Regex::new.
+    // Panics if the regex isn't valid or goes over the default limits of RegexBuilder.
+    pub fn new(regex: &str) -> Self {
+        LazyDFA::with_cache_capacity(regex, DEFAULT_CACHE_CAPACITY)
+    }
+
+    // @param capacity is the size in bytes each of the three caches (for matches, and for where a match ends
+    // and starts) may grow to.
+    pub fn with_cache_capacity(regex: &str, capacity: usize) -> Self {
+        LazyDFA::try_with_cache_capacity(regex, capacity).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like new, but returns an error instead of panicking.
+    pub fn try_new(regex: &str) -> Result<Self, Error> {
+        LazyDFA::try_with_cache_capacity(regex, DEFAULT_CACHE_CAPACITY)
+    }
+
+    // Like with_cache_capacity, but returns an error instead of panicking. The DFA isn't built up front,
+    // so dfa_size_limit doesn't apply.
+    pub fn try_with_cache_capacity(regex: &str, capacity: usize) -> Result<Self, Error> {
+        Ok(LazyDFA::from_nfa(&try_step1(regex.to_string(), &[], &Limits::default())?, capacity))
+    }
+
+    // @param nfa is the ε-NFA from step1.
+    pub(crate) fn from_nfa(nfa: &NFA, capacity: usize) -> Self {
+        let reverse_nfa = step2(nfa.reverse());
+        let nfa = step2(nfa.clone());
+        LazyDFA {
+            anchored: Cache::new(capacity, SearchState::new(&nfa, false)),
+            leftmost: Cache::new(capacity, SearchState::new(&nfa, true)),
+            reverse: Cache::new(capacity, SearchState::new(&reverse_nfa, false)),
+            nfa,
+            reverse_nfa,
+        }
+    }
+
+    // Returns true if the whole input matches.
+    pub fn matches(&mut self, input: &str) -> bool {
+        let nfa = &self.nfa;
+        let cache = &mut self.anchored;
+        let mut cursor = cache.start();
+        for ch in input.chars() {
+            cursor = cache.next(nfa, cursor, ch);
+            if cache.state(&cursor).is_dead() {
+                return false;
+            }
+        }
+        cache.state(&cursor).is_match()
+    }
+
+    // Returns true if the regex matches anywhere in the haystack.
+    pub fn is_match(&mut self, haystack: &str) -> bool {
+        let nfa = &self.nfa;
+        let cache = &mut self.leftmost;
+        let mut cursor = cache.start();
+        let mut chars = haystack.chars();
+        while !cache.state(&cursor).is_match() {
+            let ch = match chars.next() {
+                Some(ch) => ch,
+                None => return false,
+            };
+            cursor = cache.next(nfa, cursor, ch);
+        }
+        true
+    }
+
+    // Returns true if the regex matches anywhere in what the reader gives, reading it in chunks
+    // and stopping at the first match. Bytes that aren't valid UTF-8 can't be part of a match.
+    pub fn is_match_reader<R: Read>(&mut self, reader: R) -> io::Result<bool> {
+        let nfa = &self.nfa;
+        let cache = &mut self.leftmost;
+        if cache.front.is_match() {
+            return Ok(true);
+        }
+        let mut cursor = cache.start();
+        let mut pending: Vec<u8> = Vec::new();
+        let found = read_chunks(reader, |chunk| {
+            let mut found = false;
+            decode_chunk(&mut pending, chunk, |piece| {
+                let text = match piece {
+                    Ok(text) => text,
+                    // Nothing can match across them, so the search starts over after them
+                    Err(_) => {
+                        cursor = cache.start();
+                        return;
+                    }
+                };
+                for ch in text.chars() {
+                    if found {
+                        return;
+                    }
+                    // next takes the cursor, so a placeholder is left until it returns the new one
+                    cursor = cache.next(nfa, std::mem::replace(&mut cursor, Cursor::Cached(0)), ch);
+                    found = cache.state(&cursor).is_match();
+                }
+            });
+            found
+        })?;
+        Ok(found)
+    }
+
+    // Returns the start and end (byte offsets) of the leftmost-longest match, if there is one.
+    pub fn find(&mut self, haystack: &str) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0)
+    }
+
+    // Returns the start and end of the leftmost-longest match that starts at or after pos.
+    // @param pos is a byte offset and must lie on a char boundary.
+    pub fn find_at(&mut self, haystack: &str, pos: usize) -> Option<(usize, usize)> {
+        let nfa = &self.nfa;
+        let cache = &mut self.leftmost;
+        let mut cursor = cache.start();
+        let mut end = if cache.state(&cursor).is_match() { Some(pos) } else { None };
+        for (idx, ch) in haystack[pos..].char_indices() {
+            cursor = cache.next(nfa, cursor, ch);
+            let state = cache.state(&cursor);
+            if state.is_dead() {
+                break;
+            }
+            if state.is_match() {
+                end = Some(pos + idx + ch.len_utf8());
+            }
+        }
+        let end = end?;
+
+        let nfa = &self.reverse_nfa;
+        let cache = &mut self.reverse;
+        let mut cursor = cache.start();
+        let mut start = end;
+        for (idx, ch) in haystack[pos..end].char_indices().rev() {
+            cursor = cache.next(nfa, cursor, ch);
+            let state = cache.state(&cursor);
+            if state.is_dead() {
+                break;
+            }
+            if state.is_match() {
+                start = pos + idx;
+            }
+        }
+        Some((start, end))
+    }
+
+    // Number of states in the caches right now.
+    pub fn cached_states(&self) -> usize {
+        self.caches().map(|cache| cache.states.len()).sum()
+    }
+
+    // Number of times a cache was full and had to be cleared.
+    pub fn cache_clears(&self) -> usize {
+        self.caches().map(|cache| cache.clears).sum()
+    }
+
+    // Number of searches that gave up on the cache and finished by simulating the NFA.
+    pub fn nfa_fallbacks(&self) -> usize {
+        self.caches().map(|cache| cache.fallbacks).sum()
+    }
+
+    fn caches(&self) -> impl Iterator<Item = &Cache> {
+        vec![&self.anchored, &self.leftmost, &self.reverse].into_iter()
+    }
+}
+
+impl Cache {
+    fn new(capacity: usize, front: SearchState) -> Self {
+        Cache {
+            capacity,
+            front,
+            states: Vec::new(),
+            ids: HashMap::new(),
+            memory: 0,
+            clears: 0,
+            read: 0,
+            fallbacks: 0,
+        }
+    }
+
+    fn start(&mut self) -> Cursor {
+        Cursor::Cached(self.add(self.front.clone()))
+    }
+
+    fn state<'c>(&'c self, cursor: &'c Cursor) -> &'c SearchState {
+        match cursor {
+            Cursor::Cached(id) => &self.states[*id].state,
+            Cursor::Simulated(state) => state,
+        }
+    }
+
+    // Returns where the search is after reading ch. Once the cache has been cleared too often,
+    // the rest of the search simulates the NFA instead.
+    fn next(&mut self, nfa: &NFA, cursor: Cursor, ch: char) -> Cursor {
+        match cursor {
+            Cursor::Cached(id) => match self.next_cached(nfa, id, ch) {
+                Some(to) => Cursor::Cached(to),
+                None => Cursor::Simulated(self.states[id].state.next(nfa, ch)),
+            },
+            Cursor::Simulated(state) => Cursor::Simulated(state.next(nfa, ch)),
+        }
+    }
+
+    // Returns the state after reading ch, working it out if it isn't known yet.
+    // Returns None if the cache had to be cleared too often, and the NFA should be simulated instead.
+    fn next_cached(&mut self, nfa: &NFA, state: usize, ch: char) -> Option<usize> {
+        self.read += 1;
+        let paths = &self.states[state].paths;
+        let index = paths.partition_point(|(_, high, _)| *high < ch);
+        if index < paths.len() && paths[index].0 <= ch {
+            return Some(paths[index].2);
+        }
+
+        let from = self.states[state].state.clone();
+        let (low, high) = from.piece(nfa, ch);
+        let target = from.next(nfa, ch);
+        let mut state = state;
+        let added = if self.ids.contains_key(&target) { 0 } else { state_size(target.len()) };
+        if self.memory + added + PATH_SIZE > self.capacity {
+            if self.clears + 1 >= MIN_CLEARS && self.read < MIN_CHARS_PER_STATE * self.states.len() {
+                self.fallbacks += 1;
+                return None;
+            }
+            self.clear();
+            state = self.add(from);
+        }
+        let to = self.add(target);
+        let paths = &mut self.states[state].paths;
+        let index = paths.partition_point(|(_, path_high, _)| *path_high < low);
+        paths.insert(index, (low, high, to));
+        self.memory += PATH_SIZE;
+        Some(to)
+    }
+
+    // Returns the id of the state, adding it if it isn't cached.
+    fn add(&mut self, state: SearchState) -> usize {
+        if let Some(id) = self.ids.get(&state) {
+            return *id;
+        }
+        let id = self.states.len();
+        self.memory += state_size(state.len());
+        self.ids.insert(state.clone(), id);
+        self.states.push(LazyState { state, paths: Vec::new() });
+        id
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.memory = 0;
+        self.clears += 1;
+        self.read = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steps::compile;
+
+    #[test]
+    fn test_lazy_same_as_dfa() {
+        let dfa = compile("/(a|b)*abb|ж[^x]+/");
+        let mut lazy = LazyDFA::new("/(a|b)*abb|ж[^x]+/");
+        for input in ["abb", "babb", "ab", "", "жyy", "жx", "abbx"] {
+            assert_eq!(lazy.matches(input), dfa.matches(input), "{}", input);
+        }
+        assert!(lazy.is_match("xxabbxx"));
+        assert!(!lazy.is_match("xxabxx"));
+        assert!(LazyDFA::new("/z*/").is_match(""));
+        assert_eq!(LazyDFA::try_new("/a{3,1}/").err(), Some(Error::syntax("the quantifier's max is below its min")));
+        assert_eq!(lazy.find("xxbabbx"), Some((2, 6)));
+        assert_eq!(lazy.find_at("xxbabbx", 3), Some((3, 6)));
+        assert_eq!(lazy.find("жyxжzz"), Some((0, 3)));
+        assert_eq!(lazy.find("xxabx"), None);
+        // states are only built once, so searching the same inputs again doesn't add any
+        let cached = lazy.cached_states();
+        assert!(lazy.matches("babb") && lazy.is_match("xxabbxx"));
+        assert_eq!(lazy.cached_states(), cached);
+    }
+
+    #[test]
+    fn test_lazy_exponential_pattern() {
+        // the full DFA would need 2^21 nodes
+        let mut lazy = LazyDFA::new("/(a|b)*a(a|b){20}/");
+        let input = format!("{}a{}", "ab".repeat(50), "b".repeat(20));
+        assert!(lazy.matches(&input));
+        assert!(!lazy.matches(&format!("{}a{}", "ab".repeat(50), "b".repeat(21))));
+        assert!(lazy.is_match(&format!("bbb{}bbb", input)));
+        assert_eq!(lazy.find(&format!("bbb{}bbb", input)), Some((0, input.len() + 3)));
+        assert!(lazy.is_match_reader(format!("\u{ff}{}", input).as_bytes()).unwrap());
+        assert!(!lazy.is_match_reader(&b"ab\xffbbbb"[..]).unwrap());
+        assert!(lazy.cached_states() < 300);
+    }
+
+    #[test]
+    fn test_lazy_cache_clears_and_falls_back() {
+        let regex = "/(a|b)*a(a|b){12}/";
+        let inputs: Vec<String> = (0..40u32)
+            .map(|seed| (0..30).map(|i| if (seed * 7 + i * 13) % 5 < 2 { 'a' } else { 'b' }).collect())
+            .collect();
+        let dfa = compile(regex);
+        let mut small = LazyDFA::with_cache_capacity(regex, 4096);
+        for input in &inputs {
+            assert_eq!(small.matches(input), dfa.matches(input), "{}", input);
+            let anywhere = (0..input.len()).any(|start| (start..=input.len()).any(|end| dfa.matches(&input[start..end])));
+            assert_eq!(small.is_match(input), anywhere, "{}", input);
+        }
+        assert!(small.cache_clears() > 0);
+        assert!(small.nfa_fallbacks() > 0);
+    }
+}
@@ -0,0 +1,178 @@
+use automata::DfaNode;
+use automata::DFA;
+use automata::NFA;
+use error::Error;
+use error::Stage;
+use ranges;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+// What a search knows after reading part of the haystack: for each place a match could have started,
+// earliest first, the nodes of the NFA from step2 that it could be in now.
+//
+// A node that an earlier start is also in is left out of the later ones, since it would match the same
+// way from there and the earlier start wins. Once some start has a match, the ones after it are dropped
+// and no new ones are added. So while the haystack is read, the last match seen before every start has
+// died out is the end of the leftmost-longest match. An anchored search only has the start at the front.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct SearchState {
+    starts: Vec<Vec<usize>>,
+    // A new start is added after every character, until something matches
+    restart: bool,
+    is_match: bool,
+}
+
+impl SearchState {
+    pub(crate) fn new(nfa: &NFA, unanchored: bool) -> Self {
+        SearchState { starts: vec![vec![0]], restart: unanchored, is_match: false }.settle(nfa)
+    }
+
+    // A match ends right here.
+    pub(crate) fn is_match(&self) -> bool {
+        self.is_match
+    }
+
+    // No match can end here or anywhere after.
+    pub(crate) fn is_dead(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    // Number of NFA nodes in all the starts.
+    pub(crate) fn len(&self) -> usize {
+        self.starts.iter().map(|nodes| nodes.len()).sum()
+    }
+
+    // The state after reading ch.
+    pub(crate) fn next(&self, nfa: &NFA, ch: char) -> Self {
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut starts: Vec<Vec<usize>> = Vec::new();
+        for nodes in &self.starts {
+            let mut next: Vec<usize> = nodes.iter()
+                .flat_map(|id| nfa.data[id].paths.iter())
+                .filter(|(low, high, to)| *low <= ch && ch <= *high && !seen.contains(to))
+                .map(|(_, _, to)| *to)
+                .collect();
+            next.sort();
+            next.dedup();
+            seen.extend(&next);
+            if !next.is_empty() {
+                starts.push(next);
+            }
+        }
+        if self.restart && !seen.contains(&0) {
+            starts.push(vec![0]);
+        }
+        SearchState { starts, restart: self.restart, is_match: false }.settle(nfa)
+    }
+
+    // Every connection the next character could follow, including the ones of a new start.
+    fn paths<'n>(&self, nfa: &'n NFA) -> Vec<&'n (char, char, usize)> {
+        let restart: &[usize] = if self.restart { &[0] } else { &[] };
+        self.starts.iter().flatten().chain(restart).flat_map(|id| nfa.data[id].paths.iter()).collect()
+    }
+
+    // The widest range around ch that every connection the next character could follow either holds
+    // completely or not at all, so that all of its characters lead to the same state.
+    pub(crate) fn piece(&self, nfa: &NFA, ch: char) -> (char, char) {
+        let mut low = '\0';
+        let mut high = char::MAX;
+        for (path_low, path_high, _) in self.paths(nfa) {
+            if *path_high < ch {
+                low = low.max(ranges::next_char(*path_high).unwrap());
+            } else if *path_low > ch {
+                high = high.min(ranges::prev_char(*path_low).unwrap());
+            } else {
+                low = low.max(*path_low);
+                high = high.min(*path_high);
+            }
+        }
+        (low, high)
+    }
+
+    // Drops the starts after the first one with a match, and stops adding new ones.
+    fn settle(mut self, nfa: &NFA) -> Self {
+        let first = self.starts.iter().position(|nodes| nodes.iter().any(|id| nfa.data[id].is_terminal));
+        if let Some(first) = first {
+            self.starts.truncate(first + 1);
+            self.restart = false;
+            self.is_match = true;
+        }
+        self
+    }
+}
+
+// Works out every SearchState from the front, like step3 does for sets of nodes, and stops once there
+// are more than limit of them. Missing connections lead to the dead state.
+// @param nfa is the NFA from step2.
+pub(crate) fn build(nfa: &NFA, unanchored: bool, limit: usize) -> Result<DFA, Error> {
+    let mut dfa = DFA::new(nfa.regex.clone(), nfa.flags.clone());
+    let front = SearchState::new(nfa, unanchored);
+    let mut ids: HashMap<SearchState, usize> = HashMap::from([(front.clone(), 0)]);
+    let mut queue: VecDeque<SearchState> = VecDeque::from([front]);
+
+    while let Some(state) = queue.pop_front() {
+        let mut node = DfaNode::new(ids[&state]);
+        node.is_terminal = state.is_match();
+        let pieces = ranges::partition(state.paths(nfa).into_iter().map(|(low, high, _)| (*low, *high)));
+        for (low, high) in pieces {
+            let target = state.next(nfa, low);
+            if target.is_dead() {
+                continue;
+            }
+            let next_id = ids.len();
+            let target_id = *ids.entry(target.clone()).or_insert_with(|| {
+                queue.push_back(target);
+                next_id
+            });
+            if ids.len() > limit {
+                return Err(Error::TooBig { stage: Stage::Dfa, limit });
+            }
+            node.add_path(low, high, target_id);
+        }
+        dfa.add_node(node);
+    }
+    Ok(dfa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steps::step1;
+    use steps::step2;
+
+    // Where the last match ends before the search dies, the same way Regex runs the DFA.
+    fn leftmost_end(regex: &str, haystack: &str) -> Option<usize> {
+        let nfa = step2(step1(regex.to_string()));
+        let mut state = SearchState::new(&nfa, true);
+        let mut end = if state.is_match() { Some(0) } else { None };
+        for (idx, ch) in haystack.char_indices() {
+            state = state.next(&nfa, ch);
+            if state.is_dead() {
+                break;
+            }
+            if state.is_match() {
+                end = Some(idx + ch.len_utf8());
+            }
+        }
+        end
+    }
+
+    #[test]
+    fn test_leftmost_end() {
+        // the earlier start wins, even though a later one matches first
+        assert_eq!(leftmost_end("/a.*z|b/", "xa b z b"), Some(6));
+        assert_eq!(leftmost_end("/a.*z|b/", "xa b y"), Some(4));
+        assert_eq!(leftmost_end("/ab*/", "cabbbd abb"), Some(5));
+        assert_eq!(leftmost_end("/x*/", "yx"), Some(0));
+        assert_eq!(leftmost_end("/q/", "abc"), None);
+    }
+
+    #[test]
+    fn test_leftmost_build() {
+        let nfa = step2(step1("/a.*z|b/".to_string()));
+        let dfa = build(&nfa, true, usize::MAX).unwrap();
+        assert!(dfa.data.values().any(|node| node.is_terminal));
+        assert_eq!(build(&step2(step1("/(a|b)*a(a|b){8}/".to_string())), true, 100).err(), Some(Error::TooBig { stage: Stage::Dfa, limit: 100 }));
+    }
+}
@@ -1,4 +1,39 @@
-mod parse_regex;
-mod steps;
-mod automata;
+#![allow(clippy::upper_case_acronyms)]
 
+pub mod parse_regex;
+pub mod steps;
+pub mod automata;
+pub mod regex;
+pub mod captures;
+pub mod regex_set;
+pub mod operations;
+pub mod language;
+pub mod to_regex;
+pub mod generate;
+pub mod big_uint;
+pub mod random;
+pub mod dot;
+pub mod table;
+pub mod trace;
+pub mod stream;
+pub mod bytes;
+pub mod dense;
+pub mod lazy;
+pub mod error;
+pub mod pattern;
+pub mod prefilter;
+mod leftmost;
+mod ranges;
+mod substring;
+
+pub use regex::Regex;
+pub use regex::Match;
+pub use regex::RegexBuilder;
+pub use error::Error;
+pub use pattern::Pattern;
+pub use prefilter::Prefilter;
+pub use captures::Captures;
+pub use regex_set::RegexSet;
+pub use automata::StateId;
+pub use stream::DfaStream;
+pub use lazy::LazyDFA;
@@ -1,6 +1,25 @@
-pub mod automata;
-pub mod parse_regex;
-pub mod steps;
+extern crate rust_regex;
 
+use rust_regex::steps::try_compile;
+use std::env;
+use std::process;
+
+// Usage: cargo run -- "/[a-z]+/i" [input...]
+// Prints the DFA for the regex, then whether each input matches it.
 fn main() {
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        return;
+    }
+    let dfa = match try_compile(&args[0]) {
+        Ok(dfa) => dfa,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    dfa.display();
+    for input in &args[1..] {
+        println!("{}: {}", input, dfa.matches(input));
+    }
+}
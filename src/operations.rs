@@ -0,0 +1,127 @@
+use automata::DFA;
+use automata::DfaNode;
+use ranges;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use steps::step4;
+
+// Set operations on the languages of DFAs. Every result is minimized with step4.
+//
+// A missing connection means the input is rejected. The product of two DFAs splits the characters into
+// ranges that both of them treat the same way, and keeps going after one side has rejected so that
+// union, difference and complement still see the rest of the input.
+impl DFA {
+    // Accepts the strings that both DFAs accept.
+    pub fn intersect(&self, other: &DFA) -> DFA {
+        self.product(other, "&", |a, b| a && b)
+    }
+
+    // Accepts the strings that either DFA accepts.
+    pub fn union(&self, other: &DFA) -> DFA {
+        self.product(other, "|", |a, b| a || b)
+    }
+
+    // Accepts the strings this DFA accepts and the other one doesn't.
+    pub fn difference(&self, other: &DFA) -> DFA {
+        self.product(other, "-", |a, b| a && !b)
+    }
+
+    // Accepts the strings this DFA rejects, over all characters.
+    pub fn complement(&self) -> DFA {
+        let complement = self.product(self, "", |a, _| !a);
+        DFA { regex: format!("!({})", self.regex), ..complement }
+    }
+
+    // Runs both DFAs side by side. Each node of the product is a pair of nodes, where None means
+    // that DFA has already rejected the input, and the node is terminal if accept says so.
+    pub(crate) fn product(&self, other: &DFA, symbol: &str, accept: fn(bool, bool) -> bool) -> DFA {
+        let mut product = DFA::new(format!("({}) {} ({})", self.regex, symbol, other.regex),
+                                   self.flags.union(&other.flags).copied().collect());
+        let mut alphabet: Vec<(char, char)> = self.alphabet();
+        alphabet.extend(other.alphabet());
+        let alphabet = ranges::partition(alphabet);
+        let is_terminal = |dfa: &DFA, id: Option<usize>| id.is_some_and(|id| dfa.data[&id].is_terminal);
+
+        let front: (Option<usize>, Option<usize>) = (Some(0), Some(0));
+        let mut ids: HashMap<(Option<usize>, Option<usize>), usize> = HashMap::from([(front, 0)]);
+        let mut queue: VecDeque<(Option<usize>, Option<usize>)> = VecDeque::from([front]);
+        while let Some((a, b)) = queue.pop_front() {
+            let mut node = DfaNode::new(ids[&(a, b)]);
+            node.is_terminal = accept(is_terminal(self, a), is_terminal(other, b));
+            for (low, high) in &alphabet {
+                let next_a = a.and_then(|a| self.data[&a].follow(low));
+                let next_b = b.and_then(|b| other.data[&b].follow(low));
+                // Once both sides have rejected, the pair can only accept if accept(false, false) does
+                if next_a.is_none() && next_b.is_none() && !accept(false, false) {
+                    continue;
+                }
+                let next_id = ids.len();
+                let target = *ids.entry((next_a, next_b)).or_insert_with(|| {
+                    queue.push_back((next_a, next_b));
+                    next_id
+                });
+                node.add_path(*low, *high, target);
+            }
+            product.add_node(node);
+        }
+        step4(product)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+
+    #[test]
+    fn test_intersect() {
+        let both = compile("/[a-z]*x[a-z]*/").intersect(&compile("/[a-z]*y[a-z]*/"));
+        assert!(both.matches("axby"));
+        assert!(both.matches("yx"));
+        assert!(!both.matches("xx"));
+        assert!(!both.matches("y"));
+        // no string is both only digits and only letters
+        let conflict = compile(r"/\d+/").intersect(&compile("/[a-z]+/"));
+        assert_eq!(conflict.data.len(), 1);
+        assert!(!conflict.data[&0].is_terminal);
+    }
+
+    #[test]
+    fn test_union() {
+        let either = compile("/ab+/").union(&compile("/(ab)*/"));
+        assert!(either.matches(""));
+        assert!(either.matches("abbb"));
+        assert!(either.matches("ababab"));
+        assert!(!either.matches("aab"));
+    }
+
+    #[test]
+    fn test_difference() {
+        let words = compile("/[a-c]+/").difference(&compile("/a+/"));
+        assert!(words.matches("ab"));
+        assert!(words.matches("c"));
+        assert!(!words.matches("aaa"));
+        assert!(!words.matches(""));
+        assert!(!words.matches("d"));
+    }
+
+    #[test]
+    fn test_complement() {
+        let not_ab = compile("/ab/").complement();
+        assert!(not_ab.matches(""));
+        assert!(not_ab.matches("a"));
+        assert!(not_ab.matches("abab"));
+        assert!(not_ab.matches("ba"));
+        assert!(!not_ab.matches("ab"));
+        // characters the regex never mentions are complemented too
+        assert!(not_ab.matches("c"));
+        assert!(not_ab.matches("ж"));
+        assert!(!compile(r"/[\s\S]*/").complement().matches("ж"));
+        assert!(compile("/ab/").complement().complement().matches("ab"));
+    }
+
+    #[test]
+    fn test_results_are_minimized() {
+        let same = compile("/(a|b)*abb/").union(&compile("/(b|a)*abb/"));
+        assert_eq!(same.data.len(), 4);
+    }
+}
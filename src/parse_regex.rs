@@ -1,73 +1,119 @@
+use error::Error;
+use error::Stage;
+use ranges;
 use std::vec::Vec;
 use std::collections::HashSet;
-use std::collections::HashMap;
 
-enum TokenType {
+// Flag set by bytes::Regex::without_unicode. It can't be written in a regex, since the flags come
+// after the last '/'. Negated classes and '.' then match ASCII and the bytes that aren't ASCII,
+// where the byte b is written as the character RAW_BYTES + b.
+pub(crate) const NO_UNICODE_FLAG: char = '/';
+pub(crate) const RAW_BYTES: u32 = 0x10FF00;
+
+// Returns the byte that ch stands for, if it is one of the characters for raw bytes.
+pub(crate) fn raw_byte(ch: char) -> Option<u8> {
+    let value = (ch as u32).checked_sub(RAW_BYTES)?;
+    if value <= 0xFF { Some(value as u8) } else { None }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TokenType {
     CaptureGroup,
+    NonCapturingGroup,
     CharacterClass,
     Pipe,
     Normal,
 }
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Token {
-    token: String,
-    token_type: TokenType,
-    quantifier: String,
-    sub_groups: Vec<Token>,
+    pub(crate) token: String,
+    pub(crate) token_type: TokenType,
+    pub(crate) quantifier: String,
+    pub(crate) sub_groups: Vec<Token>,
+}
+// Returns Error::Syntax if the regex can't be parsed.
+pub fn process_regex(regex: &str) -> Result<(Vec<Token>, HashSet<char>), Error> {
+    process_regex_nested(regex, usize::MAX)
 }
-pub fn process_regex(regex: &str) -> (Vec<Token>, HashSet<char>) {
+
+// Like process_regex, but groups may only be nested nest_limit deep. The parser goes into a group
+// by calling itself, so the limit is checked before that, and a deeply nested regex can't use up the stack.
+pub(crate) fn process_regex_nested(regex: &str, nest_limit: usize) -> Result<(Vec<Token>, HashSet<char>), Error> {
     // index of the right most slash in the regular expression (the one before the flags)
-    let r_index = regex.rfind(|c| c == '/').unwrap();
+    let r_index = match regex.rfind('/') {
+        Some(r_index) if r_index > 0 && regex.starts_with('/') => r_index,
+        _ => return Err(Error::syntax("the regex has to be written between slashes, like /a+/")),
+    };
 
     // the flags come after the right most slash, so we separate them from the regex
     let flags = regex[r_index + 1..].chars().collect::<HashSet<char>>();
 
     // the regex is everything before the right most slash and after the first slash
     let ex = regex[1..r_index].to_string();
-    
-    (tokenize(ex), flags)
+
+    Ok((tokenize(ex, 0, nest_limit)?, flags))
 }
 
-fn split_to_parts(regex: String) -> Vec<String> {
+fn split_to_parts(regex: String) -> Result<Vec<String>, Error> {
     let mut parts: Vec<String> = Vec::new();
-    let chars: Vec<char> = regex.chars().collect();
     let mut cur_index: usize = 0;
 
-    while cur_index < chars.len() {
+    while cur_index < regex.len() {
         let mut cur_token: String = String::new();
+        let cur_char = char_at(&regex, cur_index);
 
-        if chars[cur_index] == '(' || chars[cur_index] == '[' {
-            let end_index = find_paren_match(&regex, cur_index);
+        match cur_char {
+            ')' => return Err(Error::syntax("unmatched closing parenthesis")),
+            '*' | '+' | '?' | '{' => return Err(Error::syntax("nothing to repeat")),
+            '^' | '$' => return Err(Error::syntax("anchors like ^ and $ aren't supported, escape them to match the characters")),
+            _ => {}
+        }
+        if cur_char == '(' || cur_char == '[' {
+            let end_index = find_paren_match(&regex, cur_index)?;
             cur_token.push_str(&regex[cur_index..end_index + 1]);
             cur_index = end_index + 1;
         } else {
-            if chars[cur_index] == '\\' {
-                cur_token.push(chars[cur_index]);
+            if cur_char == '\\' {
+                cur_token.push(cur_char);
                 cur_index += 1;
+                if cur_index >= regex.len() {
+                    return Err(Error::syntax("invalid escape sequence"));
+                }
             }
-            cur_token.push(chars[cur_index]);
-            cur_index += 1;
+            let cur_char = char_at(&regex, cur_index);
+            cur_token.push(cur_char);
+            cur_index += cur_char.len_utf8();
         }
         // Getting the quantifiers
-        if cur_index < chars.len() {
-            if chars[cur_index] == '*' || chars[cur_index] == '+' {
-                cur_token.push(chars[cur_index]);
+        if cur_index < regex.len() {
+            let next_char = char_at(&regex, cur_index);
+            let mut quantified = true;
+            if next_char == '*' || next_char == '+' || next_char == '?' {
+                cur_token.push(next_char);
                 cur_index += 1;
-            } else if chars[cur_index] == '{' {
-                let end_index = regex[cur_index..].find('}').unwrap() + cur_index;
+            } else if next_char == '{' {
+                let end_index = find_quantifier_end(&regex, cur_index).ok_or_else(|| Error::syntax("invalid quantifier"))?;
                 cur_token.push_str(&regex[cur_index..end_index + 1]);
                 cur_index = end_index + 1;
+            } else {
+                quantified = false;
             }
-            if cur_index < chars.len() && chars[cur_index] == '?' {
-                cur_token.push(chars[cur_index]);
+            // A trailing '?' makes the quantifier lazy
+            if quantified && cur_index < regex.len() && char_at(&regex, cur_index) == '?' {
+                cur_token.push('?');
                 cur_index += 1;
             }
+            if quantified && cur_index < regex.len() && "*+?{".contains(char_at(&regex, cur_index)) {
+                return Err(Error::syntax("a quantifier can't follow another quantifier"));
+            }
         }
         parts.push(cur_token);
     }
-    parts
+    Ok(parts)
 }
 
-fn parts_to_token(parts: Vec<String>) -> Vec<Token> {
+// @param depth is the number of groups the parts are in.
+fn parts_to_token(parts: Vec<String>, depth: usize, nest_limit: usize) -> Result<Vec<Token>, Error> {
     let mut tokens: Vec<Token> = Vec::new();
 
     for part in parts {
@@ -78,98 +124,364 @@ fn parts_to_token(parts: Vec<String>) -> Vec<Token> {
             quantifier: String::new(),
             sub_groups: Vec::new(),
         };
-        if part.as_bytes()[0] as char == '(' {
-            let end_index = find_paren_match(&part, 0);
-            let inner_component = part[1..end_index].to_string();
+        let first_char = char_at(&part, 0);
+        if first_char == '(' {
+            if depth >= nest_limit {
+                return Err(Error::TooBig { stage: Stage::Nesting, limit: nest_limit });
+            }
+            let end_index = find_paren_match(&part, 0)?;
+            let mut inner_component = part[1..end_index].to_string();
             cur_token.token_type = TokenType::CaptureGroup;
-        } else if part.as_bytes()[0] as char == '[' {
+            // (?:...) does not capture, and (?<name>...) or (?P<name>...) keeps the name in token
+            if inner_component.starts_with("?:") {
+                cur_token.token_type = TokenType::NonCapturingGroup;
+                inner_component = inner_component[2..].to_string();
+            } else if (inner_component.starts_with("?<") && !inner_component.starts_with("?<=") && !inner_component.starts_with("?<!"))
+                || inner_component.starts_with("?P<") {
+                let name_start = inner_component.find('<').unwrap() + 1;
+                let name_end = inner_component.find('>').ok_or_else(|| Error::syntax("invalid capture group name"))?;
+                cur_token.token = inner_component[name_start..name_end].to_string();
+                if cur_token.token.is_empty() {
+                    return Err(Error::syntax("invalid capture group name"));
+                }
+                inner_component = inner_component[name_end + 1..].to_string();
+            } else if inner_component.starts_with('?') {
+                // Look-arounds and inline flags aren't supported
+                return Err(Error::syntax("unknown group type"));
+            }
+            cur_token.sub_groups = tokenize(inner_component, depth + 1, nest_limit)?;
+            cur_index = end_index + 1;
+        } else if first_char == '[' {
+            let end_index = find_paren_match(&part, 0)?;
             cur_token.token_type = TokenType::CharacterClass;
+            cur_token.token = part[..end_index + 1].to_string();
+            cur_index = end_index + 1;
+        } else if first_char == '|' {
+            cur_token.token_type = TokenType::Pipe;
+            cur_token.token.push(first_char);
+            cur_index += 1;
         } else {
-            if part.as_bytes()[0] as char == '\\' {
-                cur_token.token.push(part.as_bytes()[0] as char);
+            if first_char == '\\' {
+                cur_token.token.push(first_char);
                 cur_index += 1;
             }
-            cur_token.token.push(part.as_bytes()[cur_index] as char);
-            cur_index += 1;
+            let cur_char = char_at(&part, cur_index);
+            cur_token.token.push(cur_char);
+            cur_index += cur_char.len_utf8();
         }
         cur_token.quantifier = part[cur_index..].to_string();
+        check_token(&cur_token)?;
         tokens.push(cur_token);
     }
-    todo!();
+    Ok(tokens)
+}
+
+// Alternatives at the top level are tokenized separately and joined by Pipe tokens.
+fn tokenize(regex: String, depth: usize, nest_limit: usize) -> Result<Vec<Token>, Error> {
+    if !check_pipe(&regex)? {
+        return parts_to_token(split_to_parts(regex)?, depth, nest_limit);
+    }
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut last_index: usize = 0;
+    for pipe_index in find_pipes(&regex)? {
+        tokens.extend(tokenize(regex[last_index..pipe_index].to_string(), depth, nest_limit)?);
+        tokens.extend(parts_to_token(vec!["|".to_string()], depth, nest_limit)?);
+        last_index = pipe_index + 1;
+    }
+    tokens.extend(tokenize(regex[last_index..].to_string(), depth, nest_limit)?);
+    Ok(tokens)
+}
+
+// Checks what the parser can't see while it splits the regex: the quantifier, the ranges
+// of a character class and the name of a capture group. Tokens of a Pattern are checked the same way.
+pub(crate) fn check_token(token: &Token) -> Result<(), Error> {
+    parse_quantifier(&token.quantifier)?;
+    match token.token_type {
+        TokenType::CharacterClass => {
+            class_ranges(&token.token, &HashSet::new())?;
+        }
+        TokenType::CaptureGroup if !token.token.chars().all(|c| c == '_' || c.is_alphanumeric()) => {
+            return Err(Error::syntax("invalid capture group name"));
+        }
+        _ => {}
+    }
+    Ok(())
 }
 
-fn tokenize(regex: String) -> Vec<Token> {
-    todo!();
+fn char_at(regex: &str, index: usize) -> char {
+    regex[index..].chars().next().unwrap()
 }
 
-fn find_paren_match(regex: &String, starting_index: usize) -> usize {
+// Returns the index of the bracket closing the one at starting_index.
+// Escaped characters and anything inside a character class are skipped over.
+fn find_paren_match(regex: &String, starting_index: usize) -> Result<usize, Error> {
     let end_parentheses = match regex.as_bytes()[starting_index] as char {
         '(' => ')',
         '[' => ']',
         '{' => '}',
-        _ => panic!("Invalid capture group"),
+        _ => return Err(Error::syntax("invalid capture group")),
     };
-    let mut cur_depth = 0;
+    if end_parentheses == '}' {
+        let end_index = regex[starting_index..].find('}').ok_or_else(|| Error::syntax("invalid quantifier"))?;
+        return Ok(end_index + starting_index);
+    }
 
     let mut parentheses: String = String::new();
+    let mut class_start: usize = 0;
+    let mut escaped = false;
 
-    for (idx, char) in regex.char_indices().skip(starting_index) {
-        if char == '(' || char == '[' {
-            cur_depth += 1;
+    for (idx, char) in regex[starting_index..].char_indices() {
+        let idx = idx + starting_index;
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        let in_class = parentheses.ends_with('[');
+        if char == '\\' {
+            escaped = true;
+        } else if in_class {
+            // a ']' right after the '[' (or '[^') is a literal
+            let is_first = idx == class_start + 1 || (idx == class_start + 2 && regex.as_bytes()[class_start + 1] == b'^');
+            if char == ']' && !is_first {
+                parentheses.pop();
+            }
+        } else if char == '(' {
             parentheses.push(char);
-        } else if char == end_parentheses && cur_depth == 1 {
-            return idx;
-        } else if char == ')' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
-            parentheses.pop();
-        } else if char == ']' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
+        } else if char == '[' {
+            parentheses.push(char);
+            class_start = idx;
+        } else if char == ')' && parentheses.ends_with('(') {
             parentheses.pop();
         }
+        if parentheses.is_empty() {
+            return Ok(idx);
+        }
+    }
+    if end_parentheses == ']' {
+        return Err(Error::syntax("unclosed character class"));
     }
-    panic!("Invalid capture group");
+    Err(Error::syntax("unclosed capture group"))
+}
+
+// Returns the index of the '}' closing a `{n}`, `{n,}` or `{n,m}` quantifier that starts at index.
+fn find_quantifier_end(regex: &str, index: usize) -> Option<usize> {
+    if !regex[index..].starts_with('{') {
+        return None;
+    }
+    let end_index = regex[index..].find('}')? + index;
+    let mut bounds = regex[index + 1..end_index].splitn(2, ',');
+    let min = bounds.next().unwrap_or("");
+    let max = bounds.next().unwrap_or("");
+    let is_number = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if min.is_empty() || !is_number(min) || !is_number(max) {
+        return None;
+    }
+    Some(end_index)
 }
 
 // Will check if the regex contains a pipe that is not in a capture group or character class
-fn check_pipe(regex: &String) -> bool {
-    let mut cur_depth = 0;
-    let mut parentheses: String = String::new();
-    for char in regex.chars() {
-        if char == '(' || char == '[' {
-            cur_depth += 1;
-            parentheses.push(char);
-        } else if char == ')' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
-            parentheses.pop();
-        } else if char == ']' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
-            parentheses.pop();
-        } else if char == '|' && cur_depth <= 0 {
-            return true;
+fn check_pipe(regex: &String) -> Result<bool, Error> {
+    Ok(!find_pipes(regex)?.is_empty())
+}
+
+fn find_pipes(regex: &String) -> Result<Vec<usize>, Error> {
+    let mut pipes: Vec<usize> = Vec::new();
+    let mut cur_index: usize = 0;
+    while cur_index < regex.len() {
+        let cur_char = char_at(regex, cur_index);
+        if cur_char == '(' || cur_char == '[' {
+            cur_index = find_paren_match(regex, cur_index)?;
+        } else if cur_char == '\\' {
+            cur_index += 1;
+            if cur_index >= regex.len() {
+                return Err(Error::syntax("invalid escape sequence"));
+            }
+        } else if cur_char == '|' {
+            pipes.push(cur_index);
         }
+        cur_index += char_at(regex, cur_index).len_utf8();
     }
-    false
+    Ok(pipes)
 }
 
-fn find_pipes(regex: &String) -> Vec<usize> {
-    let mut cur_depth = 0;
-    let mut parentheses: String = String::new();
-    let mut pipes: Vec<usize> = Vec::new();
-    for (idx, char) in regex.char_indices() {
-        if char == '(' || char == '[' {
-            cur_depth += 1;
-            parentheses.push(char);
-        } else if char == ')' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
-            parentheses.pop();
-        } else if char == ']' && char == parentheses.as_bytes()[parentheses.len() - 1] as char {
-            cur_depth -= 1;
-            parentheses.pop();
-        } else if char == '|' && cur_depth <= 0 {
-            pipes.push(idx);
+// Returns (min, max, lazy) for a quantifier such as "*", "+?" or "{2,5}".
+// A max of None means the token can repeat any number of times.
+pub(crate) fn parse_quantifier(quantifier: &str) -> Result<(usize, Option<usize>, bool), Error> {
+    let lazy = quantifier.len() > 1 && quantifier.ends_with('?');
+    let quantifier = if lazy { &quantifier[..quantifier.len() - 1] } else { quantifier };
+    let (min, max) = match quantifier {
+        "" => (1, Some(1)),
+        "*" => (0, None),
+        "+" => (1, None),
+        "?" => (0, Some(1)),
+        _ => {
+            let invalid = || Error::syntax("invalid quantifier");
+            if !quantifier.starts_with('{') || !quantifier.ends_with('}') {
+                return Err(invalid());
+            }
+            let inner = &quantifier[1..quantifier.len() - 1];
+            let mut bounds = inner.splitn(2, ',');
+            let min = bounds.next().unwrap().parse::<usize>().map_err(|_| invalid())?;
+            match bounds.next() {
+                None => (min, Some(min)),
+                Some("") => (min, None),
+                Some(max) => (min, Some(max.parse::<usize>().map_err(|_| invalid())?)),
+            }
+        }
+    };
+    if max.is_some_and(|max| max < min) {
+        return Err(Error::syntax("the quantifier's max is below its min"));
+    }
+    Ok((min, max, lazy))
+}
+
+// Returns every character a Normal or CharacterClass token matches, ignoring its quantifier,
+// as sorted, non-overlapping (low, high) ranges.
+pub(crate) fn token_chars(token: &Token, flags: &HashSet<char>) -> Vec<(char, char)> {
+    match token.token_type {
+        TokenType::CharacterClass => class_chars(&token.token, flags),
+        TokenType::Normal => normal_chars(&token.token, flags),
+        _ => Vec::new(),
+    }
+}
+
+fn normal_chars(token: &str, flags: &HashSet<char>) -> Vec<(char, char)> {
+    let mut chars = token.chars();
+    let ranges = match (chars.next(), chars.next()) {
+        (Some('\\'), Some(ch)) => escape_chars(ch, flags),
+        (Some('.'), None) if flags.contains(&'s') => negate(&[], flags),
+        (Some('.'), None) => negate(&[('\n', '\n')], flags),
+        (Some(ch), _) => vec![(ch, ch)],
+        _ => Vec::new(),
+    };
+    if flags.contains(&'i') {
+        return ignore_case(ranges);
+    }
+    ranges
+}
+
+pub(crate) fn escape_chars(ch: char, flags: &HashSet<char>) -> Vec<(char, char)> {
+    match ch {
+        'd' => vec![('0', '9')],
+        'w' => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        's' => vec![('\t', '\r'), (' ', ' ')],
+        'D' | 'W' | 'S' => negate(&escape_chars(ch.to_ascii_lowercase(), flags), flags),
+        'n' => vec![('\n', '\n')],
+        't' => vec![('\t', '\t')],
+        'r' => vec![('\r', '\r')],
+        _ => vec![(ch, ch)],
+    }
+}
+
+// Classes are checked with check_token when they are parsed.
+fn class_chars(class: &str, flags: &HashSet<char>) -> Vec<(char, char)> {
+    class_ranges(class, flags).expect("the class was checked when it was parsed")
+}
+
+// @param class looks like "[a-z_]" or "[^0-9]", including the brackets.
+// Returns Error::Syntax for a range that goes backwards, like [z-a].
+fn class_ranges(class: &str, flags: &HashSet<char>) -> Result<Vec<(char, char)>, Error> {
+    let inner: Vec<char> = class[1..class.len() - 1].chars().collect();
+    let negated = inner.first() == Some(&'^');
+    let mut ranges: Vec<(char, char)> = Vec::new();
+    let mut cur_index: usize = if negated { 1 } else { 0 };
+
+    while cur_index < inner.len() {
+        let (low, set, next_index) = class_item(&inner, cur_index, flags);
+        cur_index = next_index;
+        if let Some(low) = low {
+            if cur_index + 1 < inner.len() && inner[cur_index] == '-' {
+                if let (Some(high), _, after_index) = class_item(&inner, cur_index + 1, flags) {
+                    if high < low {
+                        return Err(Error::syntax("invalid character class"));
+                    }
+                    ranges.push((low, high));
+                    cur_index = after_index;
+                    continue;
+                }
+            }
         }
+        ranges.extend(set);
+    }
+    let mut ranges = ranges::normalize(ranges);
+    // The other case is added before negating, so that [^a] doesn't match 'A' either
+    if flags.contains(&'i') {
+        ranges = ignore_case(ranges);
     }
-    pipes
+    if negated {
+        return Ok(negate(&ranges, flags));
+    }
+    Ok(ranges)
+}
+
+// Reads a single character or escape inside a character class.
+// Returns the character if it can be the end of a range, what it matches, and where the next item starts.
+fn class_item(inner: &[char], index: usize, flags: &HashSet<char>) -> (Option<char>, Vec<(char, char)>, usize) {
+    if inner[index] == '\\' && index + 1 < inner.len() {
+        let set = escape_chars(inner[index + 1], flags);
+        let single = match set.as_slice() {
+            [(low, high)] if low == high => Some(*low),
+            _ => None,
+        };
+        return (single, set, index + 2);
+    }
+    (Some(inner[index]), vec![(inner[index], inner[index])], index + 1)
+}
+
+// Every character the (normalized) ranges don't hold. Without Unicode, only ASCII and the raw bytes
+// that aren't ASCII can be matched.
+pub(crate) fn negate(ranges: &[(char, char)], flags: &HashSet<char>) -> Vec<(char, char)> {
+    let complement = ranges::complement(ranges);
+    if flags.contains(&NO_UNICODE_FLAG) {
+        let raw = (char::from_u32(RAW_BYTES + 0x80).unwrap(), char::from_u32(RAW_BYTES + 0xFF).unwrap());
+        return ranges::intersect(&complement, &[('\0', '\u{7f}'), raw]);
+    }
+    complement
+}
+
+// Writes a character so that it matches only itself, inside a character class if in_class is set.
+pub(crate) fn escape_char(ch: char, in_class: bool) -> String {
+    let special = if in_class { "\\]-^[" } else { "\\.*+?()[]{}|^$/" };
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        _ if special.contains(ch) => format!("\\{}", ch),
+        _ => ch.to_string(),
+    }
+}
+
+// Escapes every character the parser treats specially, so the regex "/" + escape(text) + "/"
+// matches exactly text.
+pub fn escape(text: &str) -> String {
+    text.chars().map(|ch| escape_char(ch, false)).collect()
+}
+
+// Returns the text the tokens match if they only match that one string, like the tokens of "/a\.b/".
+// Every token has to stand for a single character and can't have a quantifier, alternative or group.
+pub(crate) fn literal_text(tokens: &[Token], flags: &HashSet<char>) -> Option<String> {
+    let mut text = String::new();
+    for token in tokens {
+        if !token.quantifier.is_empty() {
+            return None;
+        }
+        match token_chars(token, flags)[..] {
+            [(low, high)] if low == high => text.push(low),
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+// Adds the other case of every letter. Only the characters below U+20000 have another case.
+fn ignore_case(ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    let mut result = ranges.clone();
+    for (low, high) in ranges {
+        for ch in (low..=high.min('\u{1ffff}')).flat_map(|ch| ch.to_lowercase().chain(ch.to_uppercase())) {
+            result.push((ch, ch));
+        }
+    }
+    ranges::normalize(result)
 }
 
 #[cfg(test)]
@@ -180,28 +492,28 @@ mod tests {
     #[test]
     fn test_split1() {
         let regex = "a(b|c)d".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec!["a", "(b|c)", "d"]);
     }
 
     #[test]
     fn test_split_pipe_capture_star() {
         let regex = "a(b|c)*d|e".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec!["a", "(b|c)*", "d", "|", "e"]);
     }
 
     #[test]
     fn test_split_stars() {
         let regex = r"a*b+".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a*", r"b+"]);
     }
 
     #[test]
     fn test_split4() {
         let regex = r"a(b|c)*?".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         println!("{:?}", tokens);
         assert_eq!(tokens, vec![r"a", r"(b|c)*?"]);
     }
@@ -209,43 +521,73 @@ mod tests {
     #[test]
     fn test_split_question() {
         let regex = r"a?b".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a?", r"b"]);
     }
 
     #[test]
     fn test_split_backslash() {
         let regex = r"a\w".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a",r"\w"]);
     }
 
     #[test]
     fn test_split_backslash_quantifier() {
         let regex = r"a\*b".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a",r"\*", "b"]);
     }
 
     #[test]
     fn test_split_backslash_parentheses() {
         let regex = r"a\(b".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a",r"\(","b"]);
     }
 
     #[test]
     fn test_split_backslash_backslash() {
         let regex = r"a\\b".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a",r"\\","b"]);
     }
 
+    #[test]
+    fn test_tokenize_groups() {
+        let tokens = tokenize(r"(?:a)(?<year>\d+)(?P<m>b)(c)".to_string(), 0, usize::MAX).unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(types, vec![TokenType::NonCapturingGroup, TokenType::CaptureGroup, TokenType::CaptureGroup, TokenType::CaptureGroup]);
+        let names: Vec<&str> = tokens.iter().map(|t| t.token.as_str()).collect();
+        assert_eq!(names, vec!["", "year", "m", ""]);
+        assert_eq!(tokens[1].sub_groups[0].token, r"\d");
+        assert_eq!(tokens[1].sub_groups[0].quantifier, "+");
+    }
+
     #[test]
     fn test_split_quantifier() {
         let regex = r"a{1,2}b".to_string();
-        let tokens = split_to_parts(regex);
+        let tokens = split_to_parts(regex).unwrap();
         assert_eq!(tokens, vec![r"a{1,2}", "b"]);
     }
 
+    #[test]
+    fn test_escape_and_literal_text() {
+        let text = "a.b*(c)[d]{2}|^$/\\\n-";
+        let (tokens, flags) = process_regex(&format!("/{}/", escape(text))).unwrap();
+        assert_eq!(literal_text(&tokens, &flags), Some(text.to_string()));
+        let literal = |regex: &str| {
+            let (tokens, flags) = process_regex(regex).unwrap();
+            literal_text(&tokens, &flags)
+        };
+        assert_eq!(literal("//"), Some(String::new()));
+        assert_eq!(literal("/[x]1/i"), None);
+        assert_eq!(literal("/[x]1/"), Some("x1".to_string()));
+        assert_eq!(literal("/-1/i"), Some("-1".to_string()));
+        assert_eq!(literal("/a|b/"), None);
+        assert_eq!(literal("/ab?/"), None);
+        assert_eq!(literal("/a(b)/"), None);
+        assert_eq!(literal(r"/a\d/"), None);
+        assert_eq!(literal("/a./"), None);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,259 @@
+use automata::DFA;
+use error::Error;
+use parse_regex::escape_char;
+use parse_regex::Token;
+use parse_regex::TokenType;
+use regex::Regex;
+use regex::RegexBuilder;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+use steps::build_nfa;
+use steps::step2;
+use steps::step4;
+use steps::try_step3;
+use steps::Limits;
+
+// A regex built from code instead of written as a string, so nothing has to be escaped by hand:
+// Pattern::literal("a.b").then(Pattern::class('0'..='9').one_or_more()).or(Pattern::literal("x"))
+//
+// It holds the same tokens the parser makes, so it compiles through the same steps, and Display
+// writes it as a regex string like "/a\.b[0-9]+|x/" that parses back to the same tokens.
+// A mistake like a bad group name is kept until build returns it, so the methods can be chained.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    error: Option<Error>,
+}
+
+impl Pattern {
+    // Matches the text exactly. Every character becomes its own token, escaped where needed.
+    pub fn literal(text: &str) -> Self {
+        Pattern::new(text.chars().map(|ch| token(escape_char(ch, false), TokenType::Normal)).collect())
+    }
+
+    // Matches any one character in the range.
+    pub fn class(range: RangeInclusive<char>) -> Self {
+        Pattern::any_of(&[range])
+    }
+
+    // Matches any one character in one of the ranges. Without any ranges it never matches.
+    pub fn any_of(ranges: &[RangeInclusive<char>]) -> Self {
+        if ranges.is_empty() {
+            // The parser doesn't take "[]", so this is written as no character that is or isn't a space
+            return Pattern::new(vec![token(r"[^\s\S]".to_string(), TokenType::CharacterClass)]);
+        }
+        let mut class = String::from("[");
+        for range in ranges {
+            class += &escape_char(*range.start(), true);
+            if range.end() != range.start() {
+                class += "-";
+                class += &escape_char(*range.end(), true);
+            }
+        }
+        class += "]";
+        Pattern::new(vec![token(class, TokenType::CharacterClass)])
+    }
+
+    // Matches any one character except a new line, like '.'.
+    pub fn any() -> Self {
+        Pattern::new(vec![token(".".to_string(), TokenType::Normal)])
+    }
+
+    // This pattern followed by the other one.
+    pub fn then(self, other: Pattern) -> Self {
+        let error = self.error.clone().or_else(|| other.error.clone());
+        let mut tokens = self.sequence();
+        tokens.extend(other.sequence());
+        Pattern { tokens, error }
+    }
+
+    // Either this pattern or the other one.
+    pub fn or(self, other: Pattern) -> Self {
+        let mut tokens = self.tokens;
+        tokens.push(token("|".to_string(), TokenType::Pipe));
+        tokens.extend(other.tokens);
+        Pattern { tokens, error: self.error.or(other.error) }
+    }
+
+    pub fn zero_or_more(self) -> Self {
+        self.quantify("*".to_string())
+    }
+
+    pub fn one_or_more(self) -> Self {
+        self.quantify("+".to_string())
+    }
+
+    pub fn optional(self) -> Self {
+        self.quantify("?".to_string())
+    }
+
+    // Repeats the pattern at least min times, and at most max times if it is given.
+    // A max below min makes build return an error.
+    pub fn repeat(self, min: usize, max: Option<usize>) -> Self {
+        let quantifier = match max {
+            Some(max) if max == min => format!("{{{}}}", min),
+            Some(max) => format!("{{{},{}}}", min, max),
+            None => format!("{{{},}}", min),
+        };
+        self.quantify(quantifier)
+    }
+
+    // Puts the pattern in a capture group.
+    pub fn capture(self) -> Self {
+        Pattern { tokens: vec![group(String::new(), TokenType::CaptureGroup, self.tokens)], error: self.error }
+    }
+
+    // Puts the pattern in a capture group with a name, which may only have letters, digits and '_'.
+    // Any other name makes build return an error.
+    pub fn named(self, name: &str) -> Self {
+        // An empty name would be taken for a group without one, so it is turned down here
+        let error = if name.is_empty() { Some(Error::syntax("invalid capture group name")) } else { None };
+        Pattern {
+            tokens: vec![group(name.to_string(), TokenType::CaptureGroup, self.tokens)],
+            error: self.error.or(error),
+        }
+    }
+
+    // Runs all four steps on the tokens, like steps::compile.
+    // Panics if the pattern isn't valid.
+    pub fn compile(&self) -> DFA {
+        self.compile_with(&Limits::none()).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like compile, but returns an error instead of panicking if the pattern isn't valid or goes over
+    // the default limits of RegexBuilder.
+    pub fn try_compile(&self) -> Result<DFA, Error> {
+        self.compile_with(&Limits::default())
+    }
+
+    // Compiles the pattern into a Regex, with the default limits of RegexBuilder.
+    pub fn build(&self) -> Result<Regex, Error> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        RegexBuilder::new(&self.to_string()).build_tokens(&self.tokens)
+    }
+
+    fn compile_with(&self, limits: &Limits) -> Result<DFA, Error> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let nfa = build_nfa(self.to_string(), &self.tokens, HashSet::new(), limits)?;
+        Ok(step4(try_step3(step2(nfa), limits.dfa_size)?))
+    }
+
+    fn new(tokens: Vec<Token>) -> Self {
+        Pattern { tokens, error: None }
+    }
+
+    // The tokens, wrapped in a group if they have alternatives that would otherwise
+    // take in what comes before or after them.
+    fn sequence(self) -> Vec<Token> {
+        if self.tokens.iter().any(|token| token.token_type == TokenType::Pipe) {
+            return vec![group(String::new(), TokenType::NonCapturingGroup, self.tokens)];
+        }
+        self.tokens
+    }
+
+    // A quantifier can go straight on a single token that doesn't have one yet.
+    fn quantify(mut self, quantifier: String) -> Self {
+        if self.tokens.len() != 1 || !self.tokens[0].quantifier.is_empty() {
+            self.tokens = vec![group(String::new(), TokenType::NonCapturingGroup, self.tokens)];
+        }
+        self.tokens[0].quantifier = quantifier;
+        self
+    }
+}
+
+fn token(text: String, token_type: TokenType) -> Token {
+    Token { token: text, token_type, quantifier: String::new(), sub_groups: Vec::new() }
+}
+
+fn group(name: String, token_type: TokenType, sub_groups: Vec<Token>) -> Token {
+    Token { token: name, token_type, quantifier: String::new(), sub_groups }
+}
+
+// Writes the tokens back the way they would be written in a regex.
+fn write_tokens(f: &mut fmt::Formatter, tokens: &[Token]) -> fmt::Result {
+    for token in tokens {
+        match token.token_type {
+            TokenType::CaptureGroup if token.token.is_empty() => write!(f, "(")?,
+            TokenType::CaptureGroup => write!(f, "(?<{}>", token.token)?,
+            TokenType::NonCapturingGroup => write!(f, "(?:")?,
+            _ => write!(f, "{}", token.token)?,
+        }
+        if token.token_type == TokenType::CaptureGroup || token.token_type == TokenType::NonCapturingGroup {
+            write_tokens(f, &token.sub_groups)?;
+            write!(f, ")")?;
+        }
+        write!(f, "{}", token.quantifier)?;
+    }
+    Ok(())
+}
+
+// Writes the pattern as a regex string, like "/(?:ab|c)+/".
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "/")?;
+        write_tokens(f, &self.tokens)?;
+        write!(f, "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_regex::process_regex;
+
+    // The tokens of the pattern are the ones the parser makes from its Display
+    fn parses_back(pattern: &Pattern) -> String {
+        let regex = pattern.to_string();
+        assert_eq!(process_regex(&regex).unwrap().0, pattern.tokens, "{}", regex);
+        regex
+    }
+
+    #[test]
+    fn test_pattern_display() {
+        let pattern = Pattern::literal("a.b").then(Pattern::class('0'..='9').one_or_more()).or(Pattern::literal("x"));
+        assert_eq!(parses_back(&pattern), r"/a\.b[0-9]+|x/");
+        let pattern = Pattern::literal("ab").or(Pattern::literal("c")).one_or_more().then(Pattern::any());
+        assert_eq!(parses_back(&pattern), "/(?:ab|c)+./");
+        let pattern = Pattern::literal("ab").zero_or_more().repeat(2, Some(3)).named("x");
+        assert_eq!(parses_back(&pattern), "/(?<x>(?:(?:ab)*){2,3})/");
+        let pattern = Pattern::any_of(&['a'..='a', ']'..=']', '-'..='/']).capture().optional();
+        assert_eq!(parses_back(&pattern), r"/([a\]\--/])?/");
+        assert_eq!(parses_back(&Pattern::literal("(a|b)\n/").repeat(1, None)), r"/(?:\(a\|b\)\n\/){1,}/");
+    }
+
+    #[test]
+    fn test_pattern_compiles() {
+        let pattern = Pattern::literal("v").then(Pattern::class('0'..='9').repeat(1, Some(2)).named("major"))
+            .then(Pattern::literal(".").then(Pattern::class('0'..='9').one_or_more()).optional());
+        let dfa = pattern.compile();
+        assert!(dfa.matches("v12.5"));
+        assert!(dfa.matches("v1"));
+        assert!(!dfa.matches("v123"));
+        assert!(!dfa.matches("v1x5"));
+        let regex = pattern.build().unwrap();
+        assert_eq!(regex.as_str(), r"/v(?<major>[0-9]{1,2})(?:\.[0-9]+)?/");
+        assert_eq!(&regex.captures("see v3.10").unwrap()["major"], "3");
+        assert!(Pattern::literal("a").repeat(2000, None).build().is_err());
+    }
+
+    #[test]
+    fn test_pattern_errors() {
+        let syntax = |message: &str| Some(Error::Syntax { message: message.to_string() });
+        assert_eq!(Pattern::literal("a").repeat(3, Some(1)).build().err(), syntax("the quantifier's max is below its min"));
+        assert_eq!(Pattern::literal("a").named("a-b").build().err(), syntax("invalid capture group name"));
+        assert_eq!(Pattern::literal("a").named("").then(Pattern::any()).build().err(), syntax("invalid capture group name"));
+        // a class without any ranges never matches, and still parses back
+        let never = Pattern::any_of(&[]);
+        assert_eq!(parses_back(&never), r"/[^\s\S]/");
+        assert!(!never.build().unwrap().is_match("a\n b"));
+        assert!(Pattern::literal("a").or(never).build().unwrap().is_match("a"));
+        assert_eq!(Pattern::literal("a").named("").try_compile().err(), syntax("invalid capture group name"));
+        assert!(Pattern::literal("a").repeat(2000, None).try_compile().is_err());
+        assert!(Pattern::literal("ab").try_compile().unwrap().matches("ab"));
+    }
+}
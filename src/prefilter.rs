@@ -0,0 +1,178 @@
+use parse_regex::literal_text;
+use parse_regex::token_chars;
+use parse_regex::Token;
+use parse_regex::TokenType;
+use std::collections::HashSet;
+use std::fmt;
+use steps::quantifier_bounds;
+
+// A fixed string every match of a regex has to contain, which Regex looks for before running its DFAs.
+// Regex::prefilter says which one was picked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Prefilter {
+    // Nothing is required, so searches go straight to the DFAs.
+    None,
+    // The regex only matches this string, so the DFAs aren't needed at all.
+    Literal(String),
+    // Every match starts with this string: searches jump to each place it is found
+    // and check for a match there with the forward DFA.
+    Prefix(String),
+    // Every match ends with this string, so nothing after the last place it is found has to be read.
+    Suffix(String),
+    // Every match contains this string, so a haystack without it is turned down straight away.
+    Inner(String),
+}
+
+impl Prefilter {
+    // The string that is looked for, or None for Prefilter::None.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Prefilter::None => None,
+            Prefilter::Literal(text) | Prefilter::Prefix(text) | Prefilter::Suffix(text) | Prefilter::Inner(text) => Some(text),
+        }
+    }
+}
+
+// Writes the prefilter like: prefix "ERROR:"
+impl fmt::Display for Prefilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Prefilter::None => write!(f, "none"),
+            Prefilter::Literal(text) => write!(f, "literal {:?}", text),
+            Prefilter::Prefix(text) => write!(f, "prefix {:?}", text),
+            Prefilter::Suffix(text) => write!(f, "suffix {:?}", text),
+            Prefilter::Inner(text) => write!(f, "inner {:?}", text),
+        }
+    }
+}
+
+// Picks the prefilter for the tokens of a regex. After a literal pattern, a prefix is the most useful,
+// then a suffix, then an inner string.
+pub(crate) fn select(tokens: &[Token], flags: &HashSet<char>) -> Prefilter {
+    if let Some(text) = literal_text(tokens, flags) {
+        return Prefilter::Literal(text);
+    }
+    let required = sequence(tokens, flags);
+    if !required.prefix.is_empty() {
+        Prefilter::Prefix(required.prefix)
+    } else if !required.suffix.is_empty() {
+        Prefilter::Suffix(required.suffix)
+    } else if !required.inner.is_empty() {
+        Prefilter::Inner(required.inner)
+    } else {
+        Prefilter::None
+    }
+}
+
+// What is known about every string some tokens match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Required {
+    // The one string they match, if there is only one. Then prefix, suffix and inner are all this string.
+    exact: Option<String>,
+    // A string every match starts with
+    prefix: String,
+    // A string every match ends with
+    suffix: String,
+    // The longest string found that every match contains
+    inner: String,
+}
+
+impl Required {
+    fn exact(text: String) -> Self {
+        Required { exact: Some(text.clone()), prefix: text.clone(), suffix: text.clone(), inner: text }
+    }
+
+    // What is known about a match of self followed by a match of other.
+    fn then(self, other: Required) -> Self {
+        if let (Some(left), Some(right)) = (&self.exact, &other.exact) {
+            return Required::exact(format!("{}{}", left, right));
+        }
+        let prefix = if self.exact.is_some() { format!("{}{}", self.prefix, other.prefix) } else { self.prefix };
+        let suffix = if other.exact.is_some() { format!("{}{}", self.suffix, other.suffix) } else { other.suffix };
+        // Where the two meet, the end of the left side is followed by the start of the right side
+        let joined = format!("{}{}", self.suffix, other.prefix);
+        let inner = vec![self.inner, other.inner, joined, prefix.clone(), suffix.clone()].into_iter()
+            .fold(String::new(), |longest, text| if text.len() > longest.len() { text } else { longest });
+        Required { exact: None, prefix, suffix, inner }
+    }
+
+    // What is known about a match of either self or other.
+    fn or(self, other: Required) -> Self {
+        if self.exact.is_some() && self.exact == other.exact {
+            return self;
+        }
+        let prefix: String = self.prefix.chars().zip(other.prefix.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect();
+        let mut suffix: Vec<char> = self.suffix.chars().rev().zip(other.suffix.chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect();
+        suffix.reverse();
+        Required { exact: None, prefix, suffix: suffix.into_iter().collect(), inner: String::new() }
+    }
+}
+
+// Alternatives are split at the Pipe tokens, and the tokens of each one follow each other.
+fn sequence(tokens: &[Token], flags: &HashSet<char>) -> Required {
+    tokens.split(|token| token.token_type == TokenType::Pipe)
+        .map(|alternative| alternative.iter().fold(Required::exact(String::new()), |required, token| required.then(token_required(token, flags))))
+        .reduce(Required::or)
+        .unwrap_or_default()
+}
+
+fn token_required(token: &Token, flags: &HashSet<char>) -> Required {
+    let once = match token.token_type {
+        TokenType::CaptureGroup | TokenType::NonCapturingGroup => sequence(&token.sub_groups, flags),
+        _ => match token_chars(token, flags)[..] {
+            [(low, high)] if low == high => Required::exact(low.to_string()),
+            _ => Required::default(),
+        },
+    };
+    let (min, max, _) = quantifier_bounds(token);
+    if min == 0 {
+        return Required::default();
+    }
+    let mut repeated = (1..min).fold(once.clone(), |required, _| required.then(once.clone()));
+    if max != Some(min) {
+        // More copies may follow, but they start and end the same way
+        repeated.exact = None;
+    }
+    repeated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_regex::process_regex;
+
+    fn prefilter(regex: &str) -> Prefilter {
+        let (tokens, flags) = process_regex(regex).unwrap();
+        select(&tokens, &flags)
+    }
+
+    #[test]
+    fn test_select_prefilter() {
+        assert_eq!(prefilter(r"/ERROR: \d+/"), Prefilter::Prefix("ERROR: ".to_string()));
+        assert_eq!(prefilter(r"/\d+ms/"), Prefilter::Suffix("ms".to_string()));
+        assert_eq!(prefilter(r"/\w+ user=\w+/"), Prefilter::Inner(" user=".to_string()));
+        assert_eq!(prefilter("/(ab){2}c?/"), Prefilter::Prefix("abab".to_string()));
+        assert_eq!(prefilter("/a+b/"), Prefilter::Prefix("a".to_string()));
+        assert_eq!(prefilter("/(?:abc|abd)x/"), Prefilter::Prefix("ab".to_string()));
+        assert_eq!(prefilter("/[0-9](xy|zy)/"), Prefilter::Suffix("y".to_string()));
+        assert_eq!(prefilter("/[a-z]+(key|value)=[0-9]/"), Prefilter::Inner("=".to_string()));
+        assert_eq!(prefilter("/a?b*/"), Prefilter::None);
+        assert_eq!(prefilter("/ab|cd/"), Prefilter::None);
+        assert_eq!(prefilter("/abc/i"), Prefilter::None);
+        assert_eq!(prefilter(r"/a\.b/"), Prefilter::Literal("a.b".to_string()));
+        assert_eq!(prefilter("/a(b)/"), Prefilter::Prefix("ab".to_string()));
+    }
+
+    #[test]
+    fn test_prefilter_display() {
+        assert_eq!(Prefilter::Prefix("ERROR:".to_string()).to_string(), "prefix \"ERROR:\"");
+        assert_eq!(Prefilter::None.to_string(), "none");
+        assert_eq!(Prefilter::Inner("=".to_string()).text(), Some("="));
+    }
+}
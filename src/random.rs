@@ -0,0 +1,31 @@
+// A source of random numbers for DFA::sample.
+// Any generator can be used by implementing this for it.
+pub trait Rng {
+    // Returns 64 random bits.
+    fn next_u64(&mut self) -> u64;
+}
+
+// A small and fast generator (Marsaglia's xorshift64*), good enough for generating test inputs.
+// It is not suitable for anything that needs to be unpredictable.
+#[derive(Clone, Debug)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    // The same seed always gives the same numbers.
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero, or every number after it would be zero too
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        XorShift { state }
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
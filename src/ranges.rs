@@ -0,0 +1,124 @@
+// Helpers for sets of characters written as sorted (low, high) ranges, which is how the connections
+// of the automata are labelled. Surrogates aren't characters, so '\u{d7ff}' and '\u{e000}' are neighbours.
+
+// The character after ch, or None for char::MAX.
+pub(crate) fn next_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{d7ff}' => Some('\u{e000}'),
+        char::MAX => None,
+        _ => char::from_u32(ch as u32 + 1),
+    }
+}
+
+// The character before ch, or None for '\0'.
+pub(crate) fn prev_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{e000}' => Some('\u{d7ff}'),
+        '\0' => None,
+        _ => char::from_u32(ch as u32 - 1),
+    }
+}
+
+// Number of characters in low..=high.
+pub(crate) fn range_len(low: char, high: char) -> u32 {
+    let surrogates = if low <= '\u{d7ff}' && high >= '\u{e000}' { 0x800 } else { 0 };
+    high as u32 - low as u32 + 1 - surrogates
+}
+
+// Returns the character offset characters after low.
+pub(crate) fn nth_char(low: char, offset: u32) -> char {
+    let value = low as u32 + offset;
+    if low <= '\u{d7ff}' && value >= 0xD800 {
+        return char::from_u32(value + 0x800).unwrap();
+    }
+    char::from_u32(value).unwrap()
+}
+
+// Sorts the ranges and merges the ones that overlap or touch.
+pub(crate) fn normalize(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort();
+    let mut merged: Vec<(char, char)> = Vec::new();
+    for (low, high) in ranges {
+        match merged.last_mut() {
+            Some((_, last)) if next_char(*last).map_or(true, |next| low <= next) => *last = (*last).max(high),
+            _ => merged.push((low, high)),
+        }
+    }
+    merged
+}
+
+// Every character that isn't in the (normalized) ranges.
+pub(crate) fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut result: Vec<(char, char)> = Vec::new();
+    let mut next = Some('\0');
+    for (low, high) in ranges {
+        if let Some(start) = next {
+            if start < *low {
+                result.push((start, prev_char(*low).unwrap()));
+            }
+        }
+        next = next_char(*high);
+    }
+    if let Some(start) = next {
+        result.push((start, char::MAX));
+    }
+    result
+}
+
+// The characters in both (normalized) sets of ranges.
+pub(crate) fn intersect(a: &[(char, char)], b: &[(char, char)]) -> Vec<(char, char)> {
+    complement(&normalize(complement(a).into_iter().chain(complement(b)).collect()))
+}
+
+// Returns the index of the range that holds ch, in sorted, non-overlapping ranges.
+pub(crate) fn find<T>(ranges: &[(char, char, T)], ch: char) -> Option<usize> {
+    ranges.binary_search_by(|(low, high, _)| {
+        if *high < ch {
+            std::cmp::Ordering::Less
+        } else if *low > ch {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }).ok()
+}
+
+// Splits every character into pieces, so that each of the given ranges either holds a whole piece
+// or none of it. The pieces are sorted and cover all characters.
+pub(crate) fn partition<I: IntoIterator<Item = (char, char)>>(ranges: I) -> Vec<(char, char)> {
+    let mut starts: Vec<char> = vec!['\0'];
+    for (low, high) in ranges {
+        starts.push(low);
+        starts.extend(next_char(high));
+    }
+    starts.sort();
+    starts.dedup();
+    let mut pieces: Vec<(char, char)> = Vec::new();
+    for (i, start) in starts.iter().enumerate() {
+        let end = match starts.get(i + 1) {
+            Some(next) => prev_char(*next).unwrap(),
+            None => char::MAX,
+        };
+        pieces.push((*start, end));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(normalize(vec![('d', 'f'), ('a', 'b'), ('c', 'c'), ('x', 'z'), ('y', 'y')]), vec![('a', 'f'), ('x', 'z')]);
+        assert_eq!(complement(&[('\0', 'a'), ('c', char::MAX)]), vec![('b', 'b')]);
+        assert_eq!(complement(&[]), vec![('\0', char::MAX)]);
+        assert_eq!(intersect(&[('a', 'm')], &[('k', 'z')]), vec![('k', 'm')]);
+        assert_eq!(range_len('\0', char::MAX), 0x110000 - 0x800);
+        assert_eq!(nth_char('\u{d7fe}', 2), '\u{e000}');
+        assert_eq!(next_char('\u{d7ff}'), Some('\u{e000}'));
+        assert_eq!(partition(vec![('b', 'd'), ('c', 'e')]), vec![('\0', 'a'), ('b', 'b'), ('c', 'd'), ('e', 'e'), ('f', char::MAX)]);
+        assert_eq!(find(&[('a', 'c', 0), ('x', 'z', 1)], 'y'), Some(1));
+        assert_eq!(find(&[('a', 'c', 0), ('x', 'z', 1)], 'd'), None);
+    }
+}
@@ -0,0 +1,875 @@
+use automata::NFA;
+use captures::capture_slots;
+use captures::Captures;
+use dense::DenseDFA;
+use error::Error;
+use error::Stage;
+use lazy::LazyDFA;
+use lazy::DEFAULT_CACHE_CAPACITY;
+use leftmost;
+use parse_regex::escape;
+use parse_regex::process_regex_nested;
+use parse_regex::Token;
+use prefilter::select;
+use prefilter::Prefilter;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::Mutex;
+use steps::build_nfa;
+use steps::step2;
+use steps::step4;
+use steps::try_step3;
+use steps::Limits;
+use stream::DfaStream;
+use substring::Finder;
+
+// A compiled regex that can search for matches anywhere inside a string.
+//
+// Matches are leftmost-longest: the match that starts first wins, and it extends as far as it can.
+// Two DFAs are used to find them without retrying the search from every offset:
+// - leftmost reads the haystack once from the search position and stops where the leftmost match ends,
+//   @see leftmost::SearchState.
+// - reverse is anchored and reads backwards from that end to find where the match starts.
+// forward is anchored at the front, for Regex::matches and to check the places a prefix is found.
+// Capture groups are only worked out (from the ε-NFA) once a match has been found.
+// The DFAs are compiled to dense tables, so the search doesn't hash any characters. If they would have more
+// nodes than RegexBuilder::dfa_size_limit, a LazyDFA builds just the ones the searches need instead.
+// A string every match has to contain is looked for first, @see Prefilter. A pattern that only
+// matches one string, like /a\.b/, doesn't need the DFAs at all.
+pub struct Regex {
+    regex: String,
+    prefilter: Prefilter,
+    // Looks for the text of the prefilter
+    finder: Option<Finder>,
+    engine: Engine,
+    nfa: NFA,
+    names: Arc<HashMap<String, usize>>,
+}
+
+enum Engine {
+    Dense(Box<Dfas>),
+    // Searches lock it, since they add to its caches
+    Lazy(Box<Mutex<LazyDFA>>),
+}
+
+struct Dfas {
+    forward: DenseDFA,
+    // Shared with the DfaStream of is_match_reader
+    leftmost: Arc<DenseDFA>,
+    reverse: DenseDFA,
+}
+
+// Compiles a Regex with limits on how big it may get, for patterns that come from users.
+// Each setter returns the builder, so they can be chained: RegexBuilder::new("/a+/").nest_limit(10).build()
+pub struct RegexBuilder {
+    regex: String,
+    limits: Limits,
+}
+
+// A single match, where start and end are byte offsets into the searched string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl RegexBuilder {
+    // @param regex looks like: "/[a-z]+/i", the same as for Regex::new.
+    pub fn new(regex: &str) -> Self {
+        RegexBuilder {
+            regex: regex.to_string(),
+            limits: Limits::default(),
+        }
+    }
+
+    // Most nodes the ε-NFA may have (100 000 by default). Counted repetitions make a copy of
+    // what they repeat, so a{100}{100} needs 10 000 copies of a.
+    pub fn size_limit(mut self, limit: usize) -> Self {
+        self.limits.size = limit;
+        self
+    }
+
+    // Most nodes each of the DFAs may have while they are built (10 000 by default). A regex that goes over it
+    // isn't turned down: it searches with a LazyDFA, which only builds the nodes a search reaches.
+    pub fn dfa_size_limit(mut self, limit: usize) -> Self {
+        self.limits.dfa_size = limit;
+        self
+    }
+
+    // How deep groups may be nested (100 by default).
+    pub fn nest_limit(mut self, limit: usize) -> Self {
+        self.limits.nest = limit;
+        self
+    }
+
+    // Biggest number allowed in a counted repetition like {n,m} (1000 by default).
+    pub fn repeat_limit(mut self, limit: usize) -> Self {
+        self.limits.repeat = limit;
+        self
+    }
+
+    // Compiles the regex, or says why it can't: it isn't valid, or it went over one of the limits.
+    pub fn build(&self) -> Result<Regex, Error> {
+        let (tokens, flags) = process_regex_nested(&self.regex, self.limits.nest)?;
+        self.build_parsed(&tokens, flags)
+    }
+
+    // Compiles tokens that were already parsed, which must be the ones of the regex.
+    pub(crate) fn build_tokens(&self, tokens: &[Token]) -> Result<Regex, Error> {
+        self.build_parsed(tokens, HashSet::new())
+    }
+
+    fn build_parsed(&self, tokens: &[Token], flags: HashSet<char>) -> Result<Regex, Error> {
+        // build_nfa checks the tokens, which select relies on
+        let nfa = build_nfa(self.regex.clone(), tokens, flags.clone(), &self.limits)?;
+        self.build_nfa(nfa, select(tokens, &flags))
+    }
+
+    fn build_nfa(&self, nfa: NFA, prefilter: Prefilter) -> Result<Regex, Error> {
+        let engine = match self.build_dfas(&nfa) {
+            Ok(dfas) => Engine::Dense(Box::new(dfas)),
+            Err(Error::TooBig { stage: Stage::Dfa, .. }) => Engine::Lazy(Box::new(Mutex::new(LazyDFA::from_nfa(&nfa, DEFAULT_CACHE_CAPACITY)))),
+            Err(error) => return Err(error),
+        };
+        let names = nfa.capture_names.iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((name.clone()?, i)))
+            .collect();
+        Ok(Regex {
+            regex: self.regex.clone(),
+            finder: prefilter.text().map(Finder::new),
+            prefilter,
+            engine,
+            nfa,
+            names: Arc::new(names),
+        })
+    }
+
+    fn build_dfas(&self, nfa: &NFA) -> Result<Dfas, Error> {
+        let forward = step4(try_step3(step2(nfa.clone()), self.limits.dfa_size)?);
+        let leftmost = step4(leftmost::build(&step2(nfa.clone()), true, self.limits.dfa_size)?);
+        let reverse = step4(try_step3(step2(nfa.reverse()), self.limits.dfa_size)?);
+        Ok(Dfas {
+            forward: DenseDFA::new(&forward, false),
+            leftmost: Arc::new(DenseDFA::new(&leftmost, false)),
+            reverse: DenseDFA::new(&reverse, false),
+        })
+    }
+}
+
+// Iterator over the non-overlapping matches in a string, returned by Regex::find_iter.
+pub struct Matches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    last_end: Option<usize>,
+    pos: usize,
+}
+
+// Iterator over the captures of every non-overlapping match, returned by Regex::captures_iter.
+pub struct CaptureMatches<'r, 'h> {
+    matches: Matches<'r, 'h>,
+}
+
+// Iterator over the pieces of a string between matches, returned by Regex::split and Regex::splitn.
+pub struct Split<'r, 'h> {
+    matches: Matches<'r, 'h>,
+    last_end: usize,
+    remaining: usize,
+    with_captures: bool,
+    captured: VecDeque<&'h str>,
+}
+
+impl Regex {
+    // @param regex looks like: "/[a-z]+/i", the same as for step1.
+    // Panics if the regex isn't valid or goes over the default limits of RegexBuilder.
+    pub fn new(regex: &str) -> Self {
+        RegexBuilder::new(regex).build().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // A regex that matches exactly text, with every special character escaped.
+    pub fn literal(text: &str) -> Self {
+        Regex::new(&format!("/{}/", escape(text)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.regex
+    }
+
+    // Returns true if the regex only matches one string, so searches use a substring search instead of the DFAs.
+    pub fn is_literal(&self) -> bool {
+        matches!(self.prefilter, Prefilter::Literal(_))
+    }
+
+    // Returns true if the DFAs would have gone over RegexBuilder::dfa_size_limit, so a LazyDFA builds
+    // them while searching.
+    pub fn is_lazy(&self) -> bool {
+        matches!(self.engine, Engine::Lazy(_))
+    }
+
+    // The string searches look for before running the DFAs.
+    pub fn prefilter(&self) -> &Prefilter {
+        &self.prefilter
+    }
+
+    // Returns true if the whole input matches, the same as DFA::matches.
+    pub fn matches(&self, input: &str) -> bool {
+        if let Prefilter::Literal(text) = &self.prefilter {
+            return input == text;
+        }
+        match &self.engine {
+            Engine::Dense(dfas) => dfas.forward.matches(input),
+            Engine::Lazy(lazy) => lazy.lock().unwrap().matches(input),
+        }
+    }
+
+    // Returns true if the regex matches anywhere in the haystack.
+    // Stops at the first place a match ends, without working out where it starts.
+    pub fn is_match(&self, haystack: &str) -> bool {
+        match (&self.prefilter, &self.finder) {
+            (Prefilter::Literal(_), Some(finder)) => return finder.find(haystack, 0).is_some(),
+            // Every prefilter string has to be in a haystack with a match
+            (_, Some(finder)) if finder.find(haystack, 0).is_none() => return false,
+            _ => {}
+        }
+        match &self.engine {
+            Engine::Dense(dfas) => dfas.is_match(haystack),
+            Engine::Lazy(lazy) => lazy.lock().unwrap().is_match(haystack),
+        }
+    }
+
+    // Returns true if the regex matches anywhere in what the reader gives, reading it in chunks
+    // and stopping at the first match. Until the first match, the leftmost DFA is in a terminal node
+    // wherever a match ends, so the DfaStream runs it instead of building a DFA of its own.
+    // A lazy regex stays locked while it reads, so other searches with it wait until it is done.
+    pub fn is_match_reader<R: Read>(&self, reader: R) -> io::Result<bool> {
+        match &self.engine {
+            Engine::Dense(dfas) => DfaStream::with_dfa(dfas.leftmost.clone()).is_match_reader(reader),
+            Engine::Lazy(lazy) => lazy.lock().unwrap().is_match_reader(reader),
+        }
+    }
+
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    // Returns the leftmost-longest match that starts at or after pos.
+    // @param pos is a byte offset and must lie on a char boundary.
+    pub fn find_at<'h>(&self, haystack: &'h str, pos: usize) -> Option<Match<'h>> {
+        match (&self.prefilter, &self.finder) {
+            (Prefilter::Literal(text), Some(finder)) => {
+                let start = finder.find(haystack, pos)?;
+                return Some(Match { haystack, start, end: start + text.len() });
+            }
+            (Prefilter::Prefix(_), Some(finder)) => return self.find_by_prefix(haystack, pos, finder),
+            // Every match ends by the end of the last suffix
+            (Prefilter::Suffix(text), Some(finder)) => {
+                let end = finder.rfind(haystack, pos)? + text.len();
+                return self.find_before(haystack, pos, end);
+            }
+            (Prefilter::Inner(_), Some(finder)) if finder.find(haystack, pos).is_none() => return None,
+            _ => {}
+        }
+        self.find_before(haystack, pos, haystack.len())
+    }
+
+    // Returns an iterator over all non-overlapping matches, from left to right.
+    // An empty match that starts where the previous match ended is skipped.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches {
+            regex: self,
+            haystack,
+            last_end: None,
+            pos: 0,
+        }
+    }
+
+    // Number of capture groups, including group 0 for the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.nfa.capture_names.len()
+    }
+
+    // Name of every capture group in order, with None for unnamed groups (and group 0).
+    pub fn capture_names(&self) -> Vec<Option<&str>> {
+        self.nfa.capture_names.iter().map(|name| name.as_deref()).collect()
+    }
+
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    // Returns the captures of the leftmost-longest match that starts at or after pos.
+    pub fn captures_at<'h>(&self, haystack: &'h str, pos: usize) -> Option<Captures<'h>> {
+        let found = self.find_at(haystack, pos)?;
+        Some(self.captures_of(found, true))
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches { matches: self.find_iter(haystack) }
+    }
+
+    // Returns an iterator over the pieces of the haystack between matches.
+    // Matches are found the same way as find_iter, so an empty match splits between two characters.
+    pub fn split<'r, 'h>(&'r self, haystack: &'h str) -> Split<'r, 'h> {
+        self.splitn(haystack, usize::MAX)
+    }
+
+    // Like split, but returns at most limit pieces. The last piece is the rest of the haystack.
+    pub fn splitn<'r, 'h>(&'r self, haystack: &'h str, limit: usize) -> Split<'r, 'h> {
+        Split {
+            matches: self.find_iter(haystack),
+            last_end: 0,
+            remaining: limit,
+            with_captures: false,
+            captured: VecDeque::new(),
+        }
+    }
+
+    // Replaces the first match with the template, see Captures::expand for the syntax.
+    pub fn replace<'h>(&self, haystack: &'h str, template: &str) -> Cow<'h, str> {
+        self.replacen(haystack, 1, template)
+    }
+
+    pub fn replace_all<'h>(&self, haystack: &'h str, template: &str) -> Cow<'h, str> {
+        self.replacen(haystack, 0, template)
+    }
+
+    // Replaces the first limit matches with the template, or all of them if limit is 0.
+    // The haystack is only copied if something was replaced.
+    pub fn replacen<'h>(&self, haystack: &'h str, limit: usize, template: &str) -> Cow<'h, str> {
+        // Without a $ there is nothing to expand, so the groups don't need to be found
+        let uses_groups = template.contains('$');
+        self.replace_with(haystack, limit, uses_groups, |caps: &Captures| {
+            let mut replacement = String::new();
+            caps.expand(template, &mut replacement);
+            replacement
+        })
+    }
+
+    // Replaces every match with whatever the closure returns for its captures.
+    pub fn replace_all_with<'h, F, T>(&self, haystack: &'h str, replacer: F) -> Cow<'h, str>
+        where F: FnMut(&Captures) -> T, T: AsRef<str> {
+        self.replace_with(haystack, 0, true, replacer)
+    }
+
+    fn replace_with<'h, F, T>(&self, haystack: &'h str, limit: usize, uses_groups: bool, mut replacer: F) -> Cow<'h, str>
+        where F: FnMut(&Captures) -> T, T: AsRef<str> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut output = String::new();
+        let mut last_end: usize = 0;
+        let mut replaced = false;
+        for found in self.find_iter(haystack).take(limit) {
+            output.push_str(&haystack[last_end..found.start]);
+            output.push_str(replacer(&self.captures_of(found, uses_groups)).as_ref());
+            last_end = found.end;
+            replaced = true;
+        }
+        if !replaced {
+            return Cow::Borrowed(haystack);
+        }
+        output.push_str(&haystack[last_end..]);
+        Cow::Owned(output)
+    }
+
+    // Works out the capture groups of a match. If groups is false only group 0 is filled in.
+    fn captures_of<'h>(&self, found: Match<'h>, groups: bool) -> Captures<'h> {
+        let slots = if groups && self.captures_len() > 1 {
+            capture_slots(&self.nfa, found.haystack, found.start, found.end)
+        } else {
+            let mut slots = vec![None; self.captures_len() * 2];
+            slots[0] = Some(found.start);
+            slots[1] = Some(found.end);
+            slots
+        };
+        Captures::new(found.haystack, slots, self.names.clone())
+    }
+
+    // Jumps to every place the prefix is found and checks for a match there with the forward DFA.
+    // Once the checks have read more than the rest of the haystack, the leftmost DFA takes over,
+    // so a prefix that is found very often can't make the search much slower than without it.
+    fn find_by_prefix<'h>(&self, haystack: &'h str, pos: usize, finder: &Finder) -> Option<Match<'h>> {
+        let dfas = match &self.engine {
+            Engine::Dense(dfas) => dfas,
+            // No match starts before the first prefix
+            Engine::Lazy(_) => return self.find_before(haystack, finder.find(haystack, pos)?, haystack.len()),
+        };
+        let mut budget = haystack.len() - pos;
+        let mut from = pos;
+        while let Some(start) = finder.find(haystack, from) {
+            let (found, read) = dfas.forward_match(haystack, start);
+            if found.is_some() {
+                return found;
+            }
+            if read >= budget {
+                return self.find_before(haystack, start, haystack.len());
+            }
+            budget -= read;
+            // The prefix can't start in the middle of a character, so this won't find one there
+            from = start + 1;
+        }
+        None
+    }
+
+    // Finds the leftmost-longest match in haystack[pos..end]. The leftmost DFA finds where it ends,
+    // then the reverse DFA reads back from there to the earliest place it can start.
+    fn find_before<'h>(&self, haystack: &'h str, pos: usize, end: usize) -> Option<Match<'h>> {
+        match &self.engine {
+            Engine::Dense(dfas) => dfas.find_before(haystack, pos, end),
+            Engine::Lazy(lazy) => {
+                let (start, end) = lazy.lock().unwrap().find_at(&haystack[..end], pos)?;
+                Some(Match { haystack, start, end })
+            }
+        }
+    }
+}
+
+impl Dfas {
+    // Runs the leftmost DFA until the first match ends.
+    fn is_match(&self, haystack: &str) -> bool {
+        let mut state = self.leftmost.start_state();
+        if self.leftmost.is_match_state(state) {
+            return true;
+        }
+        for ch in haystack.chars() {
+            state = self.leftmost.next_state(state, ch);
+            if self.leftmost.is_match_state(state) {
+                return true;
+            }
+            if self.leftmost.is_dead_state(state) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn find_before<'h>(&self, haystack: &'h str, pos: usize, end: usize) -> Option<Match<'h>> {
+        let mut state = self.leftmost.start_state();
+        let mut found = if self.leftmost.is_match_state(state) { Some(pos) } else { None };
+        for (idx, ch) in haystack[pos..end].char_indices() {
+            state = self.leftmost.next_state(state, ch);
+            if self.leftmost.is_dead_state(state) {
+                break;
+            }
+            if self.leftmost.is_match_state(state) {
+                found = Some(pos + idx + ch.len_utf8());
+            }
+        }
+        let end = found?;
+
+        let mut state = self.reverse.start_state();
+        let mut start = end;
+        for (idx, ch) in haystack[pos..end].char_indices().rev() {
+            state = self.reverse.next_state(state, ch);
+            if self.reverse.is_dead_state(state) {
+                break;
+            }
+            if self.reverse.is_match_state(state) {
+                start = pos + idx;
+            }
+        }
+        Some(Match { haystack, start, end })
+    }
+
+    // Runs the forward DFA from start for as long as it can. Returns the longest match it saw, if there is one,
+    // and how many bytes it read.
+    fn forward_match<'h>(&self, haystack: &'h str, start: usize) -> (Option<Match<'h>>, usize) {
+        let mut state = self.forward.start_state();
+        let mut end = if self.forward.is_match_state(state) { Some(start) } else { None };
+        let mut read: usize = 0;
+        for (idx, ch) in haystack[start..].char_indices() {
+            state = self.forward.next_state(state, ch);
+            read = idx + ch.len_utf8();
+            if self.forward.is_dead_state(state) {
+                break;
+            }
+            if self.forward.is_match_state(state) {
+                end = Some(start + read);
+            }
+        }
+        (end.map(|end| Match { haystack, start, end }), read)
+    }
+}
+
+impl<'h> Match<'h> {
+    pub(crate) fn new(haystack: &'h str, start: usize, end: usize) -> Self {
+        Match { haystack, start, end }
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.start..self.end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl<'r, 'h> Iterator for Matches<'r, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        loop {
+            let found = self.regex.find_at(self.haystack, self.pos)?;
+            let start = found.start;
+
+            if found.is_empty() && self.last_end == Some(found.end) {
+                // Step over one character so the same empty match isn't found again
+                let ch = self.haystack[start..].chars().next()?;
+                self.pos = start + ch.len_utf8();
+                continue;
+            }
+            self.pos = found.end;
+            self.last_end = Some(found.end);
+            return Some(found);
+        }
+    }
+}
+
+impl<'r, 'h> Iterator for CaptureMatches<'r, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Captures<'h>> {
+        let found = self.matches.next()?;
+        Some(self.matches.regex.captures_of(found, true))
+    }
+}
+
+impl<'r, 'h> Split<'r, 'h> {
+    // Also returns the delimiters: after each piece comes the text of every capture group that matched
+    // in the delimiter, or the whole delimiter if the regex has no groups.
+    // These don't count towards the limit of splitn.
+    pub fn with_captures(mut self) -> Self {
+        self.with_captures = true;
+        self
+    }
+}
+
+impl<'r, 'h> Iterator for Split<'r, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        if let Some(captured) = self.captured.pop_front() {
+            return Some(captured);
+        }
+        let haystack = self.matches.haystack;
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let found = match self.matches.next() {
+            Some(found) if self.remaining > 0 => found,
+            // The last piece is everything that is left
+            _ => {
+                self.remaining = 0;
+                return Some(&haystack[self.last_end..]);
+            }
+        };
+        let piece = &haystack[self.last_end..found.start];
+        self.last_end = found.end;
+        if self.with_captures {
+            let regex = self.matches.regex;
+            let caps = regex.captures_of(found, true);
+            if caps.len() == 1 {
+                self.captured.push_back(found.as_str());
+            }
+            for i in 1..caps.len() {
+                if let Some(group) = caps.get(i) {
+                    self.captured.push_back(group.as_str());
+                }
+            }
+        }
+        Some(piece)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_all(regex: &str, haystack: &str) -> Vec<(usize, usize)> {
+        Regex::new(regex).find_iter(haystack).map(|m| (m.start, m.end)).collect()
+    }
+
+    #[test]
+    fn test_find_leftmost_longest() {
+        let regex = Regex::new("/ab|bcdef/");
+        let found = regex.find("xabcdef").unwrap();
+        assert_eq!((found.start, found.end), (1, 3));
+        assert_eq!(found.as_str(), "ab");
+        // b matches first, but the match starting at a started earlier
+        assert_eq!(find_all("/a.*z|b/", "xa b z b"), vec![(1, 6), (7, 8)]);
+        assert_eq!(find_all("/a.*z|b/", "xa b y"), vec![(3, 4)]);
+        assert!(Regex::new("/a.*z|b/").is_match("xa b y"));
+    }
+
+    #[test]
+    fn test_find_longest() {
+        let regex = Regex::new("/a+/");
+        assert_eq!(regex.find("bbaaab").unwrap().as_str(), "aaa");
+        assert!(regex.find("bbb").is_none());
+    }
+
+    #[test]
+    fn test_find_at() {
+        let regex = Regex::new(r"/\d+/");
+        let found = regex.find_at("12 345", 1).unwrap();
+        assert_eq!((found.start, found.end), (1, 2));
+        let found = regex.find_at("12 345", 2).unwrap();
+        assert_eq!(found.as_str(), "345");
+        assert!(regex.find_at("12 345", 6).is_none());
+    }
+
+    #[test]
+    fn test_find_iter() {
+        assert_eq!(find_all("/a|b/", "abcab"), vec![(0, 1), (1, 2), (3, 4), (4, 5)]);
+        assert_eq!(find_all(r"/\w+/", "hi there, you"), vec![(0, 2), (3, 8), (10, 13)]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches() {
+        assert_eq!(find_all("/a*/", "baaab"), vec![(0, 0), (1, 4), (5, 5)]);
+        assert_eq!(find_all("/x*/", ""), vec![(0, 0)]);
+        assert_eq!(find_all("/x*/", "ab"), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_find_iter_utf8() {
+        assert_eq!(find_all("/x*/", "é"), vec![(0, 0), (2, 2)]);
+        assert_eq!(find_all("/é+/", "aéé b é"), vec![(1, 5), (8, 10)]);
+        let regex = Regex::new("/b/");
+        let found: Vec<&str> = regex.find_iter("☃b☃bb").map(|m| m.as_str()).collect();
+        assert_eq!(found, vec!["b", "b", "b"]);
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new(r"/(\w+)=(\d+)/");
+        assert_eq!(regex.replace("a=1 b=2", "$2=$1"), "1=a b=2");
+        assert_eq!(regex.replace_all("a=1 b=2", "$2=$1"), "1=a 2=b");
+        assert_eq!(regex.replacen("a=1 b=2 c=3", 2, "[$0]"), "[a=1] [b=2] c=3");
+        assert_eq!(regex.replace_all("a=1", "$$"), "$");
+    }
+
+    #[test]
+    fn test_replace_named_and_closure() {
+        let regex = Regex::new(r"/user=(?<name>\w+)/");
+        assert_eq!(regex.replace_all("user=bob user=amy", "user=<${name}>"), "user=<bob> user=<amy>");
+        let upper = regex.replace_all_with("user=bob, user=amy", |caps: &Captures| caps["name"].to_uppercase());
+        assert_eq!(upper, "BOB, AMY");
+    }
+
+    #[test]
+    fn test_replace_without_match_borrows() {
+        let regex = Regex::new("/x+/");
+        assert!(matches!(regex.replace_all("abc", "y"), Cow::Borrowed("abc")));
+        assert!(matches!(regex.replace_all("axc", "y"), Cow::Owned(_)));
+        assert_eq!(Regex::new("/b*/").replace_all("abc", "-"), "-a-c-");
+    }
+
+    #[test]
+    fn test_split() {
+        let regex = Regex::new(r"/\s*,\s*/");
+        let pieces: Vec<&str> = regex.split("a , b,c ,, d").collect();
+        assert_eq!(pieces, vec!["a", "b", "c", "", "d"]);
+        let pieces: Vec<&str> = regex.split("").collect();
+        assert_eq!(pieces, vec![""]);
+        let pieces: Vec<&str> = regex.split(",a,").collect();
+        assert_eq!(pieces, vec!["", "a", ""]);
+    }
+
+    #[test]
+    fn test_split_empty_matches() {
+        let pieces: Vec<&str> = Regex::new("/x*/").split("abc").collect();
+        assert_eq!(pieces, vec!["", "a", "b", "c", ""]);
+        let pieces: Vec<&str> = Regex::new("/b*/").split("abbc").collect();
+        assert_eq!(pieces, vec!["", "a", "c", ""]);
+    }
+
+    #[test]
+    fn test_splitn() {
+        let regex = Regex::new("/,/");
+        let pieces: Vec<&str> = regex.splitn("a,b,c,d", 2).collect();
+        assert_eq!(pieces, vec!["a", "b,c,d"]);
+        let pieces: Vec<&str> = regex.splitn("a,b", 5).collect();
+        assert_eq!(pieces, vec!["a", "b"]);
+        assert_eq!(regex.splitn("a,b", 0).count(), 0);
+        let pieces: Vec<&str> = regex.splitn("a,b", 1).collect();
+        assert_eq!(pieces, vec!["a,b"]);
+    }
+
+    #[test]
+    fn test_split_with_captures() {
+        let regex = Regex::new(r"/\s*([,;])\s*/");
+        let pieces: Vec<&str> = regex.split("a, b ;c").with_captures().collect();
+        assert_eq!(pieces, vec!["a", ",", "b", ";", "c"]);
+        let pieces: Vec<&str> = Regex::new("/[,;]/").splitn("a,b;c", 2).with_captures().collect();
+        assert_eq!(pieces, vec!["a", ",", "b;c"]);
+    }
+
+    #[test]
+    fn test_is_match() {
+        let regex = Regex::new("/[a-c]{2}/i");
+        assert!(regex.is_match("xxBCxx"));
+        assert!(!regex.is_match("xxbxcx"));
+        assert!(!regex.matches("xxBCxx"));
+        assert!(regex.matches("Ab"));
+    }
+
+    #[test]
+    fn test_builder_limits() {
+        use error::Stage;
+        let too_big = |stage, limit| Some(Error::TooBig { stage, limit });
+        assert!(RegexBuilder::new(r"/\d{3}-\d{4}/").build().unwrap().matches("555-1234"));
+        assert_eq!(RegexBuilder::new("/((a))/").nest_limit(1).build().err(), too_big(Stage::Nesting, 1));
+        assert!(RegexBuilder::new("/(a)(b)/").nest_limit(1).build().is_ok());
+        // the parser stops before it goes any deeper, instead of running out of stack
+        let nested = format!("/{}a{}/", "(".repeat(50000), ")".repeat(50000));
+        assert_eq!(RegexBuilder::new(&nested).nest_limit(10).build().err(), too_big(Stage::Nesting, 10));
+        let nested = format!("/{}a{}/", "(?:".repeat(200), ")".repeat(200));
+        assert_eq!(RegexBuilder::new(&nested).build().err(), too_big(Stage::Nesting, 100));
+        assert_eq!(RegexBuilder::new("/a{1001}/").build().err(), too_big(Stage::Repetition, 1000));
+        assert_eq!(RegexBuilder::new("/(a{100}){100}/").size_limit(5000).build().err(), too_big(Stage::Nfa, 5000));
+        // the DFA needs 2^14 nodes to remember the last 14 characters, so it is built while searching instead
+        assert!(RegexBuilder::new("/(a|b)*a(a|b){13}/").build().unwrap().is_lazy());
+        assert!(RegexBuilder::new("/(a|b)*a(a|b){5}/").dfa_size_limit(50).build().unwrap().is_lazy());
+        assert!(!RegexBuilder::new("/(a|b)*a(a|b){5}/").dfa_size_limit(100).build().unwrap().is_lazy());
+        assert_eq!(Error::TooBig { stage: Stage::Dfa, limit: 10_000 }.to_string(), "the DFA would have more than 10000 nodes");
+    }
+
+    #[test]
+    fn test_lazy_fallback() {
+        let regex = RegexBuilder::new("/(a|b)*a(a|b){20}/").build().unwrap();
+        assert!(regex.is_lazy());
+        let haystack = format!("xx{}a{}x", "ab".repeat(5), "b".repeat(20));
+        let found = regex.find(&haystack).unwrap();
+        assert_eq!((found.start, found.end), (2, haystack.len() - 1));
+        assert!(regex.is_match(&haystack));
+        assert!(!regex.is_match(&format!("a{}", "b".repeat(19))));
+        assert!(regex.is_match_reader(haystack.as_bytes()).unwrap());
+        assert!(regex.matches(&haystack[2..haystack.len() - 1]));
+        // the lazy DFA finds the same matches as the dense tables
+        for &(pattern, haystack) in [
+            ("/a.*z|b/", "xa b z b"),
+            (r"/ERROR: \d+/", "ERROR: 12 ERROR: x ERROR: 3"),
+            (r"/\d+ms/", "5ms 12ms xms"),
+            ("/x*/", "ab"),
+            ("/(?<n>[0-9]+)-(?<m>[a-z]*)/", "12-ab 3- 45x-"),
+            ("/é+|ж/", "aéé жb é"),
+        ].iter() {
+            let lazy = RegexBuilder::new(pattern).dfa_size_limit(1).build().unwrap();
+            assert!(lazy.is_lazy());
+            let expected: Vec<(usize, usize)> = find_all(pattern, haystack);
+            let found: Vec<(usize, usize)> = lazy.find_iter(haystack).map(|found| (found.start, found.end)).collect();
+            assert_eq!(found, expected, "{}", pattern);
+            assert_eq!(lazy.replace_all(haystack, "<$0>"), Regex::new(pattern).replace_all(haystack, "<$0>"));
+        }
+    }
+
+    #[test]
+    fn test_builder_syntax_errors() {
+        let syntax = |message: &str| Some(Error::Syntax { message: message.to_string() });
+        assert_eq!(RegexBuilder::new("/(a/").build().err(), syntax("unclosed capture group"));
+        assert_eq!(RegexBuilder::new("/[ab/").build().err(), syntax("unclosed character class"));
+        assert_eq!(RegexBuilder::new("/a{3,1}/").build().err(), syntax("the quantifier's max is below its min"));
+        assert_eq!(RegexBuilder::new("/a{99999999999999999999}/").build().err(), syntax("invalid quantifier"));
+        assert_eq!(RegexBuilder::new("abc").build().err(), syntax("the regex has to be written between slashes, like /a+/"));
+        assert_eq!(RegexBuilder::new("/(?<a>x)(?<a>y)/").build().err(), syntax("duplicate capture group name"));
+        assert_eq!(RegexBuilder::new("/(?<a-b>x)/").build().err(), syntax("invalid capture group name"));
+        assert_eq!(RegexBuilder::new("/(?<>x)/").build().err(), syntax("invalid capture group name"));
+        assert_eq!(RegexBuilder::new("/[z-a]/").build().err(), syntax("invalid character class"));
+        assert_eq!(RegexBuilder::new(r"/a\/").build().err(), syntax("invalid escape sequence"));
+        assert_eq!(RegexBuilder::new("/(a/").build().err().unwrap().to_string(), "invalid regex: unclosed capture group");
+    }
+
+    #[test]
+    fn test_builder_rejects_malformed_syntax() {
+        let syntax = |message: &str| Some(Error::Syntax { message: message.to_string() });
+        let stacked = syntax("a quantifier can't follow another quantifier");
+        assert_eq!(RegexBuilder::new("/a**/").build().err(), stacked);
+        assert_eq!(RegexBuilder::new("/x{1}{2}/").build().err(), stacked);
+        assert_eq!(RegexBuilder::new("/a+??/").build().err(), stacked);
+        assert_eq!(RegexBuilder::new("/*a/").build().err(), syntax("nothing to repeat"));
+        assert_eq!(RegexBuilder::new("/a|+b/").build().err(), syntax("nothing to repeat"));
+        assert_eq!(RegexBuilder::new("/a{,3}/").build().err(), syntax("invalid quantifier"));
+        assert_eq!(RegexBuilder::new("/a{2/").build().err(), syntax("invalid quantifier"));
+        assert_eq!(RegexBuilder::new("/(?=x)/").build().err(), syntax("unknown group type"));
+        assert_eq!(RegexBuilder::new("/(?<!x)y/").build().err(), syntax("unknown group type"));
+        assert_eq!(RegexBuilder::new("/a)/").build().err(), syntax("unmatched closing parenthesis"));
+        let anchors = syntax("anchors like ^ and $ aren't supported, escape them to match the characters");
+        assert_eq!(RegexBuilder::new("/^a/").build().err(), anchors);
+        assert_eq!(RegexBuilder::new("/a$/").build().err(), anchors);
+        // escaped, in a class, or after a quantifier that is lazy, they are fine
+        assert!(Regex::new(r"/\^a\$[$^]/").is_match("x^a$$"));
+        assert!(Regex::new("/a+?b{1,2}?/").is_match("aab"));
+        assert!(Regex::literal("^{1}$").is_match("^{1}$"));
+    }
+
+    #[test]
+    fn test_literal() {
+        let regex = Regex::literal("a.b(1)");
+        assert!(regex.is_literal());
+        assert_eq!(regex.as_str(), r"/a\.b\(1\)/");
+        assert_eq!(find_all(r"/a\.b\(1\)/", "a.b(1)xa.b(1)axb(1)"), vec![(0, 6), (7, 13)]);
+        assert!(regex.matches("a.b(1)"));
+        assert!(!regex.matches("a.b(1)x"));
+        assert!(!regex.is_match("axb(1)"));
+        assert_eq!(Regex::literal("ж").replace_all("жaжж", "-"), "-a--");
+        assert_eq!(Regex::literal("a").split("bab").collect::<Vec<_>>(), vec!["b", "b"]);
+        // an empty literal matches between every two characters, the same as without the fast path
+        assert_eq!(find_all("//", "aж"), vec![(0, 0), (1, 1), (3, 3)]);
+        assert!(Regex::new("/x[y]z/").is_literal());
+        assert!(!Regex::new("/xyz/i").is_literal());
+        assert!(!Regex::new("/x(y)z/").is_literal());
+    }
+
+    #[test]
+    fn test_prefilter_finds_same_matches() {
+        use steps::compile;
+        let cases = [
+            (r"/ERROR: \d+/", Prefilter::Prefix("ERROR: ".to_string())),
+            ("/a[ab]*b/", Prefilter::Prefix("a".to_string())),
+            (r"/\d+ms/", Prefilter::Suffix("ms".to_string())),
+            (r"/[a-z]+=\w*/", Prefilter::Inner("=".to_string())),
+        ];
+        let haystacks = ["ERROR: 12 ERROR: x ERROR: 3", "aaab abba ab", "5ms 12ms xms 7 ms", "a= b=c =d", "", "жa=ж"];
+        for (regex, prefilter) in &cases {
+            let found = Regex::new(regex);
+            assert_eq!(found.prefilter(), prefilter);
+            let dfa = compile(regex);
+            for haystack in &haystacks {
+                let bounds: Vec<usize> = (0..=haystack.len()).filter(|i| haystack.is_char_boundary(*i)).collect();
+                for pos in &bounds {
+                    // the first start with a match, and its longest one
+                    let expected = bounds.iter().filter(|start| *start >= pos).find_map(|start| {
+                        let end = bounds.iter().rev().find(|end| *end >= start && dfa.matches(&haystack[*start..**end]))?;
+                        Some((*start, *end))
+                    });
+                    assert_eq!(found.find_at(haystack, *pos).map(|m| (m.start, m.end)), expected, "{} {:?} {}", regex, haystack, pos);
+                }
+            }
+        }
+        assert_eq!(find_all(r"/ERROR: \d+/", "ERROR: 12 ERROR: x ERROR: 3"), vec![(0, 9), (19, 27)]);
+        assert_eq!(find_all(r"/\d+ms/", "5ms 12ms xms"), vec![(0, 3), (4, 8)]);
+        assert_eq!(Regex::new("/a?b*/").prefilter(), &Prefilter::None);
+        assert_eq!(Regex::literal("user=").prefilter().to_string(), "literal \"user=\"");
+    }
+
+    #[test]
+    fn test_prefix_with_many_candidates() {
+        // every 'a' is a candidate that reads to the end, so the reverse DFA takes over
+        let haystack = format!("{}b", "a".repeat(2000));
+        let regex = Regex::new("/a[^c]*bb/");
+        assert_eq!(regex.prefilter(), &Prefilter::Prefix("a".to_string()));
+        assert_eq!(regex.find(&haystack), None);
+        assert_eq!(regex.find(&format!("{}b", haystack)).map(|m| (m.start, m.end)), Some((0, 2002)));
+    }
+}
@@ -0,0 +1,128 @@
+use automata::DFA;
+use automata::NFA;
+use error::Error;
+use std::collections::BTreeSet;
+use steps::step2;
+use steps::step4;
+use steps::try_step1;
+use steps::try_step3;
+use steps::Limits;
+
+// Matches many regexes at once with a single DFA.
+//
+// The NFAs of all the regexes are combined before steps 2 to 4, so every DFA node knows which of
+// the regexes it accepts. A single pass over the input then finds every regex that matches anywhere in it.
+pub struct RegexSet {
+    patterns: Vec<String>,
+    dfa: DFA,
+}
+
+impl RegexSet {
+    // @param patterns each look like: "/[a-z]+/i", the same as for Regex::new.
+    // Panics if one of them isn't valid or goes over the default limits of RegexBuilder.
+    pub fn new<I, S>(patterns: I) -> Self where I: IntoIterator<Item = S>, S: AsRef<str> {
+        RegexSet::try_new(patterns).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like new, but returns the error of the first pattern that isn't valid, or the one of the combined DFA
+    // if it goes over dfa_size_limit.
+    pub fn try_new<I, S>(patterns: I) -> Result<Self, Error> where I: IntoIterator<Item = S>, S: AsRef<str> {
+        let limits = Limits::default();
+        let patterns: Vec<String> = patterns.into_iter().map(|pattern| pattern.as_ref().to_string()).collect();
+        let nfas = patterns.iter()
+            .map(|pattern| try_step1(pattern.clone(), &[], &limits))
+            .collect::<Result<Vec<NFA>, Error>>()?;
+        let dfa = step4(try_step3(step2(NFA::combine(nfas).unanchored()), limits.dfa_size)?);
+        Ok(RegexSet { patterns, dfa })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    // Returns true if any of the regexes matches anywhere in the haystack.
+    pub fn is_match(&self, haystack: &str) -> bool {
+        !self.scan(haystack, true).is_empty()
+    }
+
+    // Returns the index of every regex that matches anywhere in the haystack, in increasing order.
+    pub fn matches(&self, haystack: &str) -> Vec<usize> {
+        self.scan(haystack, false).into_iter().collect()
+    }
+
+    // Runs the DFA over the haystack once, collecting the patterns of every terminal node it passes.
+    // Stops early once every pattern has matched, or on the first match if first_only is set.
+    fn scan(&self, haystack: &str, first_only: bool) -> BTreeSet<usize> {
+        let mut matched: BTreeSet<usize> = BTreeSet::new();
+        let mut current_node = self.dfa.get_node(&0).unwrap();
+        matched.extend(&current_node.patterns);
+        for ch in haystack.chars() {
+            if matched.len() == self.patterns.len() || (first_only && !matched.is_empty()) {
+                break;
+            }
+            // Characters without a connection can only be skipped over, which leaves the front node
+            let next_id = current_node.follow(&ch).unwrap_or(0);
+            current_node = self.dfa.get_node(&next_id).unwrap();
+            matched.extend(&current_node.patterns);
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Stage;
+
+    #[test]
+    fn test_set_matches() {
+        let set = RegexSet::new(["/ERROR/", r"/user=\w+/", r"/\d{3}/", "/warn/i"]);
+        assert_eq!(set.matches("ERROR: user=bob"), vec![0, 1]);
+        assert_eq!(set.matches("code 404, WARNING"), vec![2, 3]);
+        assert_eq!(set.matches("all good"), Vec::<usize>::new());
+        assert!(set.is_match("ERROR"));
+        assert!(!set.is_match("error"));
+    }
+
+    #[test]
+    fn test_set_overlapping_patterns() {
+        let set = RegexSet::new(["/ab/", "/abc/", "/b+c/", "/x*/"]);
+        assert_eq!(set.matches("abc"), vec![0, 1, 2, 3]);
+        assert_eq!(set.matches("zbbc"), vec![2, 3]);
+        assert_eq!(set.matches(""), vec![3]);
+    }
+
+    #[test]
+    fn test_set_shares_one_dfa() {
+        let set = RegexSet::new(["/cat/", "/car/", "/cart/"]);
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.matches("a cart"), vec![1, 2]);
+        // minimization still merges nodes that accept the same patterns
+        let words = RegexSet::new(["/(a|b)*abb/", "/(b|a)*abb/"]);
+        assert_eq!(words.dfa.data.len(), 4);
+        assert_eq!(words.matches("babb"), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_set_empty() {
+        let set = RegexSet::new(Vec::<String>::new());
+        assert!(set.is_empty());
+        assert!(!set.is_match("anything"));
+    }
+
+    #[test]
+    fn test_set_try_new() {
+        assert_eq!(RegexSet::try_new(["/a/", "/(b/"]).err(), Some(Error::syntax("unclosed capture group")));
+        assert_eq!(RegexSet::try_new(["/a{2000}/"]).err(), Some(Error::TooBig { stage: Stage::Repetition, limit: 1000 }));
+        assert_eq!(RegexSet::try_new(["/a/", "/b/"]).unwrap().matches("b"), vec![1]);
+        assert!(RegexSet::try_new(["/a$/", "/^b/"]).is_err());
+    }
+}
@@ -1,28 +1,633 @@
 use automata::NFA;
+use automata::NfaNode;
 use automata::DFA;
-use parse_regex::tokenize_regex;
+use automata::DfaNode;
+use error::Error;
+use error::Stage;
+use parse_regex::check_token;
+use parse_regex::process_regex_nested;
+use parse_regex::parse_quantifier;
+use parse_regex::token_chars;
+use parse_regex::Token;
+use ranges;
+use parse_regex::TokenType;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
 
 // @param regex looks like: "/[a-z]+/i", where the part between the slashes is the regex,
 // and the last characters are the flags.
 // Goal of step 1 is to convert the regex to a NFA with epsilon connections;
-fn step1(regex: String) -> NFA {
-    todo!();
+// Panics if the regex isn't valid, @see try_step1 for an error instead.
+pub(crate) fn step1(regex: String) -> NFA {
+    try_step1(regex, &[], &Limits::none()).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// How big a pattern may get while it is compiled, set with RegexBuilder.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Limits {
+    // Nodes of the ε-NFA
+    pub(crate) size: usize,
+    // Nodes made by the subset construction in step3
+    pub(crate) dfa_size: usize,
+    // Depth of nested groups
+    pub(crate) nest: usize,
+    // Bounds of counted repetitions
+    pub(crate) repeat: usize,
+}
+
+impl Limits {
+    pub(crate) fn none() -> Self {
+        Limits { size: usize::MAX, dfa_size: usize::MAX, nest: usize::MAX, repeat: usize::MAX }
+    }
+}
+
+// The limits of a new RegexBuilder, which the try_ constructors use for patterns that come from users.
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { size: 100_000, dfa_size: 10_000, nest: 100, repeat: 1000 }
+    }
+}
+
+// Step 1, checking the syntax and the limits before anything is built.
+pub(crate) fn try_step1(regex: String, extra_flags: &[char], limits: &Limits) -> Result<NFA, Error> {
+    let (tokens, mut flags) = process_regex_nested(&regex, limits.nest)?;
+    flags.extend(extra_flags);
+    build_nfa(regex, &tokens, flags, limits)
+}
+
+// Builds the ε-NFA for tokens that were already parsed, like the ones of a Pattern.
+// @param regex is only kept for display.
+pub(crate) fn build_nfa(regex: String, tokens: &[Token], flags: HashSet<char>, limits: &Limits) -> Result<NFA, Error> {
+    check_tokens(tokens, limits, 1)?;
+    // Node 0, which leads to the rest
+    if sequence_size(tokens).saturating_add(1) > limits.size {
+        return Err(Error::TooBig { stage: Stage::Nfa, limit: limits.size });
+    }
+    let mut nfa = NFA::new(regex, flags);
+    collect_capture_names(tokens, &mut nfa.capture_names)?;
+    let (start, end) = build_sequence(&mut nfa, tokens, 1);
+    nfa.add_epsilon(0, start);
+    nfa.data.get_mut(&end).unwrap().is_terminal = true;
+    Ok(nfa)
+}
+
+// Checks the nesting and repetition limits, and that tokens which didn't come from the parser are valid.
+// @param depth is the depth of groups that the tokens are in, counting themselves.
+fn check_tokens(tokens: &[Token], limits: &Limits, depth: usize) -> Result<(), Error> {
+    for token in tokens {
+        check_token(token)?;
+        let (min, max, _) = parse_quantifier(&token.quantifier)?;
+        if min.max(max.unwrap_or(0)) > limits.repeat {
+            return Err(Error::TooBig { stage: Stage::Repetition, limit: limits.repeat });
+        }
+        if token.token_type == TokenType::CaptureGroup || token.token_type == TokenType::NonCapturingGroup {
+            if depth > limits.nest {
+                return Err(Error::TooBig { stage: Stage::Nesting, limit: limits.nest });
+            }
+            check_tokens(&token.sub_groups, limits, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+// Number of nodes build_sequence adds for the tokens, worked out without building them.
+// Saturates instead of overflowing, since it is only compared with the size limit.
+fn sequence_size(tokens: &[Token]) -> usize {
+    let alternatives: Vec<&[Token]> = tokens.split(|token| token.token_type == TokenType::Pipe).collect();
+    if alternatives.len() > 1 {
+        return alternatives.iter().fold(2, |size, alternative| size.saturating_add(sequence_size(alternative)));
+    }
+    tokens.iter().fold(1, |size, token| size.saturating_add(token_size(token)))
+}
+
+// Number of nodes build_token adds.
+fn token_size(token: &Token) -> usize {
+    let (min, max, _) = quantifier_bounds(token);
+    let atom = match token.token_type {
+        TokenType::NonCapturingGroup => sequence_size(&token.sub_groups),
+        TokenType::CaptureGroup => sequence_size(&token.sub_groups).saturating_add(2),
+        _ => 2,
+    };
+    let copies = match max {
+        None => min.saturating_add(1),
+        Some(max) => max,
+    };
+    // The start node and the exit node
+    atom.saturating_mul(copies).saturating_add(2)
+}
+
+// The quantifier of a token that check_tokens has already been through.
+pub(crate) fn quantifier_bounds(token: &Token) -> (usize, Option<usize>, bool) {
+    parse_quantifier(&token.quantifier).expect("the quantifier was checked before building")
 }
 
 // @param nfa is the NFA from step1.
 // Goal of step 2 is to convert the NFA to a NFA without epsilon connections;
-fn step2(nfa: NFA) -> NFA {
-    todo!();
+pub(crate) fn step2(nfa: NFA) -> NFA {
+    let mut result = NFA::new(nfa.regex.clone(), nfa.flags.clone());
+    for id in nfa.data.keys() {
+        let mut node = NfaNode::new(*id);
+        for member in nfa.epsilon_closure(*id) {
+            let member = &nfa.data[&member];
+            node.is_terminal |= member.is_terminal;
+            node.patterns.extend(&member.patterns);
+            node.paths.extend(&member.paths);
+        }
+        result.add_node(node);
+    }
+    result
 }
 
 // @param nfa is the NFA from step2.
 // Goal of step 3 is to convert the NFA to a DFA by reworking duplicate connections;
-fn step3(nfa: NFA) -> DFA {
-    todo!();
+// Overlapping ranges are split into pieces that lead to the same set of nodes.
+// A DFA node accepts every pattern that any of its NFA nodes accepts.
+pub(crate) fn step3(nfa: NFA) -> DFA {
+    try_step3(nfa, usize::MAX).expect("there is no limit to go over")
+}
+
+// Step 3, stopping once the DFA has more than limit nodes.
+pub(crate) fn try_step3(nfa: NFA, limit: usize) -> Result<DFA, Error> {
+    let mut dfa = DFA::new(nfa.regex.clone(), nfa.flags.clone());
+    let too_big = Error::TooBig { stage: Stage::Dfa, limit };
+
+    // Nothing to rework if no node has duplicate connections
+    if nfa.data.values().all(|node| node.is_dfa()) {
+        if nfa.data.len() > limit {
+            return Err(too_big);
+        }
+        for node in nfa.data.values() {
+            dfa.add_node(DfaNode::from(node.clone()));
+        }
+        return Ok(dfa);
+    }
+
+    // Every DFA node stands for the set of NFA nodes the input could be in
+    let front: BTreeSet<usize> = BTreeSet::from([0]);
+    let mut ids: HashMap<BTreeSet<usize>, usize> = HashMap::from([(front.clone(), 0)]);
+    let mut queue: VecDeque<BTreeSet<usize>> = VecDeque::from([front]);
+
+    while let Some(set) = queue.pop_front() {
+        let mut node = DfaNode::new(ids[&set]);
+        let mut paths: Vec<(char, char, usize)> = Vec::new();
+        for member in &set {
+            let member = &nfa.data[member];
+            node.is_terminal |= member.is_terminal;
+            node.patterns.extend(&member.patterns);
+            paths.extend(&member.paths);
+        }
+        paths.sort();
+        for (low, high) in ranges::partition(paths.iter().map(|(low, high, _)| (*low, *high))) {
+            let target: BTreeSet<usize> = paths.iter()
+                .take_while(|(path_low, _, _)| *path_low <= low)
+                .filter(|(_, path_high, _)| *path_high >= low)
+                .map(|(_, _, to)| *to)
+                .collect();
+            if target.is_empty() {
+                continue;
+            }
+            let next_id = ids.len();
+            let target_id = *ids.entry(target.clone()).or_insert_with(|| {
+                queue.push_back(target);
+                next_id
+            });
+            if ids.len() > limit {
+                return Err(too_big);
+            }
+            node.add_path(low, high, target_id);
+        }
+        dfa.add_node(node);
+    }
+    Ok(dfa)
 }
 
 // @param dfa is the DFA from step3.
 // Goal of step 4 is to remove redundant & unreachable nodes.
-fn step4(dfa: DFA) -> DFA {
-    todo!();
-}
\ No newline at end of file
+pub(crate) fn step4(dfa: DFA) -> DFA {
+    // Nodes that can be reached from the front and that can still reach a terminal node
+    let reachable = reachable_nodes(&dfa);
+    let mut useful: HashSet<usize> = dfa.data.values()
+        .filter(|node| node.is_terminal && reachable.contains(&node.id))
+        .map(|node| node.id)
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in &reachable {
+            let node = &dfa.data[id];
+            if !useful.contains(id) && node.paths.iter().any(|(_, _, to)| useful.contains(to)) {
+                useful.insert(*id);
+                changed = true;
+            }
+        }
+    }
+
+    // Split the useful nodes into groups until every node in a group behaves the same way
+    let alphabet: Vec<(char, char)> = dfa.alphabet();
+    // Nodes that accept different patterns can never be merged
+    let mut accepts: HashMap<(bool, &BTreeSet<usize>), usize> = HashMap::new();
+    let mut group: HashMap<usize, usize> = HashMap::new();
+    for id in &useful {
+        let node = &dfa.data[id];
+        let next_id = accepts.len();
+        group.insert(*id, *accepts.entry((node.is_terminal, &node.patterns)).or_insert(next_id));
+    }
+    let mut group_count = 0;
+    loop {
+        let mut signatures: HashMap<(usize, Vec<Option<usize>>), usize> = HashMap::new();
+        let mut ordered: Vec<&usize> = useful.iter().collect();
+        ordered.sort();
+        let mut new_group: HashMap<usize, usize> = HashMap::new();
+        for id in ordered {
+            let node = &dfa.data[id];
+            let signature: Vec<Option<usize>> = alphabet.iter()
+                .map(|(low, _)| node.follow(low).and_then(|to| group.get(&to)).copied())
+                .collect();
+            let next_id = signatures.len();
+            let id_group = *signatures.entry((group[id], signature)).or_insert(next_id);
+            new_group.insert(*id, id_group);
+        }
+        group = new_group;
+        if signatures.len() == group_count {
+            break;
+        }
+        group_count = signatures.len();
+    }
+
+    // Rebuild the DFA from the groups, numbering them in the order they are reached
+    let mut result = DFA::new(dfa.regex.clone(), dfa.flags.clone());
+    let mut front = DfaNode::new(0);
+    front.is_terminal = dfa.data[&0].is_terminal;
+    if !useful.contains(&0) {
+        result.add_node(front);
+        return result;
+    }
+    let mut ids: HashMap<usize, usize> = HashMap::from([(group[&0], 0)]);
+    let mut queue: VecDeque<usize> = VecDeque::from([0]);
+    while let Some(old_id) = queue.pop_front() {
+        let old_node = &dfa.data[&old_id];
+        let mut node = DfaNode::new(ids[&group[&old_id]]);
+        node.is_terminal = old_node.is_terminal;
+        node.patterns = old_node.patterns.clone();
+        for (low, high) in &alphabet {
+            let to = match old_node.follow(low) {
+                Some(to) if useful.contains(&to) => to,
+                _ => continue,
+            };
+            let next_id = ids.len();
+            let new_to = *ids.entry(group[&to]).or_insert_with(|| {
+                queue.push_back(to);
+                next_id
+            });
+            node.add_path(*low, *high, new_to);
+        }
+        result.add_node(node);
+    }
+    result
+}
+
+fn reachable_nodes(dfa: &DFA) -> HashSet<usize> {
+    let mut reachable: HashSet<usize> = HashSet::from([0]);
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(id) = stack.pop() {
+        for (_, _, to) in &dfa.data[&id].paths {
+            if reachable.insert(*to) {
+                stack.push(*to);
+            }
+        }
+    }
+    reachable
+}
+
+// Runs all four steps.
+pub fn compile(regex: &str) -> DFA {
+    step4(step3(step2(step1(regex.to_string()))))
+}
+
+// Like compile, but returns an error instead of panicking if the regex isn't valid or goes over
+// the default limits of RegexBuilder.
+pub fn try_compile(regex: &str) -> Result<DFA, Error> {
+    let limits = Limits::default();
+    let nfa = try_step1(regex.to_string(), &[], &limits)?;
+    Ok(step4(try_step3(step2(nfa), limits.dfa_size)?))
+}
+
+// The automaton after each of the four steps, and how long each step took. Returned by compile_traced.
+// The DFAs can be printed with DFA::to_table, and every stage can be drawn with to_dot.
+pub struct CompileTrace {
+    pub epsilon_nfa: NFA,
+    pub nfa: NFA,
+    pub dfa: DFA,
+    pub minimized: DFA,
+    // One entry per step, in order
+    pub stats: Vec<StepStats>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepStats {
+    pub step: &'static str,
+    pub states: usize,
+    // Number of connections, counting each range of characters once and including ε connections
+    pub edges: usize,
+    pub time: Duration,
+}
+
+// Runs all four steps like compile, but keeps the result of each of them.
+pub fn compile_traced(regex: &str) -> CompileTrace {
+    trace_steps(|| Ok(step1(regex.to_string())), usize::MAX).expect("there is no limit to go over")
+}
+
+// Like compile_traced, but returns an error instead of panicking if the regex isn't valid or goes over
+// the default limits of RegexBuilder.
+pub fn try_compile_traced(regex: &str) -> Result<CompileTrace, Error> {
+    let limits = Limits::default();
+    trace_steps(|| try_step1(regex.to_string(), &[], &limits), limits.dfa_size)
+}
+
+// Runs the four steps and times each of them.
+// @param step1 makes the ε-NFA.
+fn trace_steps<F: FnOnce() -> Result<NFA, Error>>(step1: F, dfa_limit: usize) -> Result<CompileTrace, Error> {
+    let nfa_stats = |step: &'static str, nfa: &NFA, time: Duration| StepStats {
+        step,
+        states: nfa.data.len(),
+        edges: nfa.data.values().map(|node| node.paths.len() + node.epsilons.len()).sum(),
+        time,
+    };
+    let dfa_stats = |step: &'static str, dfa: &DFA, time: Duration| StepStats {
+        step,
+        states: dfa.data.len(),
+        edges: dfa.data.values().map(|node| node.paths.len()).sum(),
+        time,
+    };
+
+    let start = Instant::now();
+    let epsilon_nfa = step1()?;
+    let mut stats = vec![nfa_stats("ε-NFA", &epsilon_nfa, start.elapsed())];
+    let start = Instant::now();
+    let nfa = step2(epsilon_nfa.clone());
+    stats.push(nfa_stats("NFA", &nfa, start.elapsed()));
+    let start = Instant::now();
+    let dfa = try_step3(nfa.clone(), dfa_limit)?;
+    stats.push(dfa_stats("DFA", &dfa, start.elapsed()));
+    let start = Instant::now();
+    let minimized = step4(dfa.clone());
+    stats.push(dfa_stats("minimized DFA", &minimized, start.elapsed()));
+    Ok(CompileTrace { epsilon_nfa, nfa, dfa, minimized, stats })
+}
+
+// A table of the stats, one line per step.
+impl fmt::Display for CompileTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14} {:>7} {:>7} {:>12}", "step", "states", "edges", "time")?;
+        for stats in &self.stats {
+            writeln!(f, "{:<14} {:>7} {:>7} {:>12}", stats.step, stats.states, stats.edges, format!("{:?}", stats.time))?;
+        }
+        Ok(())
+    }
+}
+
+// Capture groups are numbered by their opening parenthesis, from left to right.
+fn collect_capture_names(tokens: &[Token], names: &mut Vec<Option<String>>) -> Result<(), Error> {
+    for token in tokens {
+        if token.token_type == TokenType::CaptureGroup {
+            let name = if token.token.is_empty() { None } else { Some(token.token.clone()) };
+            if name.is_some() && names.contains(&name) {
+                return Err(Error::syntax("duplicate capture group name"));
+            }
+            names.push(name);
+        }
+        collect_capture_names(&token.sub_groups, names)?;
+    }
+    Ok(())
+}
+
+fn count_capture_groups(tokens: &[Token]) -> usize {
+    tokens.iter()
+        .map(|token| (token.token_type == TokenType::CaptureGroup) as usize + count_capture_groups(&token.sub_groups))
+        .sum()
+}
+
+// Builds a piece of the NFA for a list of tokens, returning its first and last node.
+// Pipe tokens split the list into alternatives.
+// @param group is the number of the first capture group inside the tokens.
+fn build_sequence(nfa: &mut NFA, tokens: &[Token], group: usize) -> (usize, usize) {
+    let alternatives: Vec<&[Token]> = tokens.split(|token| token.token_type == TokenType::Pipe).collect();
+    if alternatives.len() > 1 {
+        let start = nfa.new_node();
+        let end = nfa.new_node();
+        let mut group = group;
+        for alternative in alternatives {
+            let (alt_start, alt_end) = build_sequence(nfa, alternative, group);
+            nfa.add_epsilon(start, alt_start);
+            nfa.add_epsilon(alt_end, end);
+            group += count_capture_groups(alternative);
+        }
+        return (start, end);
+    }
+
+    let start = nfa.new_node();
+    let mut end = start;
+    let mut group = group;
+    for token in tokens {
+        let (token_start, token_end) = build_token(nfa, token, group);
+        nfa.add_epsilon(end, token_start);
+        end = token_end;
+        group += count_capture_groups(std::slice::from_ref(token));
+    }
+    (start, end)
+}
+
+// Builds a token together with its quantifier, e.g. a{2,3} becomes aa(a)?
+// Every copy of a group captures into the same slots, so the last repetition wins.
+fn build_token(nfa: &mut NFA, token: &Token, group: usize) -> (usize, usize) {
+    let (min, max, lazy) = quantifier_bounds(token);
+    let start = nfa.new_node();
+    let mut end = start;
+    for _ in 0..min {
+        let (copy_start, copy_end) = build_atom(nfa, token, group);
+        nfa.add_epsilon(end, copy_start);
+        end = copy_end;
+    }
+
+    match max {
+        // Loop back to the start of a new copy for as long as the input allows
+        None => {
+            let (copy_start, copy_end) = build_atom(nfa, token, group);
+            let exit = nfa.new_node();
+            add_choice(nfa, end, copy_start, exit, lazy);
+            add_choice(nfa, copy_end, copy_start, exit, lazy);
+            end = exit;
+        }
+        // Every extra copy can be skipped, which skips all the copies after it as well
+        Some(max) => {
+            let exit = nfa.new_node();
+            for _ in min..max {
+                let (copy_start, copy_end) = build_atom(nfa, token, group);
+                add_choice(nfa, end, copy_start, exit, lazy);
+                end = copy_end;
+            }
+            nfa.add_epsilon(end, exit);
+            end = exit;
+        }
+    }
+    (start, end)
+}
+
+// Adds ε connections from `from` to both `repeat` and `exit`, preferring to repeat unless lazy.
+fn add_choice(nfa: &mut NFA, from: usize, repeat: usize, exit: usize, lazy: bool) {
+    if lazy {
+        nfa.add_epsilon(from, exit);
+        nfa.add_epsilon(from, repeat);
+    } else {
+        nfa.add_epsilon(from, repeat);
+        nfa.add_epsilon(from, exit);
+    }
+}
+
+// Builds a single copy of a token, ignoring its quantifier.
+fn build_atom(nfa: &mut NFA, token: &Token, group: usize) -> (usize, usize) {
+    if token.token_type == TokenType::NonCapturingGroup {
+        return build_sequence(nfa, &token.sub_groups, group);
+    }
+    if token.token_type == TokenType::CaptureGroup {
+        let (inner_start, inner_end) = build_sequence(nfa, &token.sub_groups, group + 1);
+        let start = nfa.new_node();
+        let end = nfa.new_node();
+        nfa.data.get_mut(&start).unwrap().capture = Some(group * 2);
+        nfa.data.get_mut(&end).unwrap().capture = Some(group * 2 + 1);
+        nfa.add_epsilon(start, inner_start);
+        nfa.add_epsilon(inner_end, end);
+        return (start, end);
+    }
+    let start = nfa.new_node();
+    let end = nfa.new_node();
+    let flags = nfa.flags.clone();
+    for (low, high) in token_chars(token, &flags) {
+        nfa.add_path(start, low, high, end);
+    }
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_regex::process_regex;
+
+    #[test]
+    fn test_compile_concat_and_pipe() {
+        let dfa = compile("/ab|cd/");
+        assert!(dfa.matches("ab"));
+        assert!(dfa.matches("cd"));
+        assert!(!dfa.matches("ad"));
+        assert!(!dfa.matches(""));
+    }
+
+    #[test]
+    fn test_compile_star_group() {
+        let dfa = compile("/(a|b)*c/");
+        assert!(dfa.matches("c"));
+        assert!(dfa.matches("abbac"));
+        assert!(!dfa.matches("abba"));
+        assert!(!dfa.matches("ca"));
+    }
+
+    #[test]
+    fn test_compile_counted_repetition() {
+        let dfa = compile("/a{2,3}b{2}c{1,}/");
+        assert!(dfa.matches("aabbc"));
+        assert!(dfa.matches("aaabbccc"));
+        assert!(!dfa.matches("abbc"));
+        assert!(!dfa.matches("aaaabbc"));
+        assert!(!dfa.matches("aabbbc"));
+        assert!(!dfa.matches("aabb"));
+    }
+
+    #[test]
+    fn test_compile_classes_and_escapes() {
+        let dfa = compile(r"/[a-c_]\d?\.[^x]/");
+        assert!(dfa.matches("_1.y"));
+        assert!(dfa.matches("b.."));
+        assert!(!dfa.matches("d1.y"));
+        assert!(!dfa.matches("a1.x"));
+        assert!(!dfa.matches("a1xy"));
+    }
+
+    #[test]
+    fn test_compile_flags() {
+        let dfa = compile("/he(l+)o/i");
+        assert!(dfa.matches("HeLLo"));
+        assert!(!dfa.matches("Heo"));
+        assert!(compile("/a.b/s").matches("a\nb"));
+        assert!(!compile("/a.b/").matches("a\nb"));
+    }
+
+    #[test]
+    fn test_compile_ranges() {
+        // negated classes and '.' cover every character, not only ASCII
+        let dfa = compile("/[^a-c]./");
+        assert!(dfa.matches("жx"));
+        assert!(dfa.matches("\0\u{10ffff}"));
+        assert!(!dfa.matches("bx"));
+        assert!(!dfa.matches("x\n"));
+        // overlapping ranges are split into pieces, which are merged again where they agree
+        let dfa = compile("/[a-m]x|[h-z]y/");
+        assert!(dfa.matches("hx") && dfa.matches("hy") && dfa.matches("ax") && dfa.matches("zy"));
+        assert!(!dfa.matches("ay") && !dfa.matches("zx"));
+        assert_eq!(dfa.data[&0].paths.len(), 3);
+        assert!(compile("/[^a]/i").matches("b") && !compile("/[^a]/i").matches("A"));
+    }
+
+    #[test]
+    fn test_nfa_size_estimate() {
+        for regex in ["/ab|cd/", "/(a|b)*c{2,4}/", "/(?:x(y)+)?z{3}|w/", "//"] {
+            let (tokens, _) = process_regex(regex).unwrap();
+            assert_eq!(sequence_size(&tokens) + 1, step1(regex.to_string()).data.len(), "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_step4_minimizes() {
+        // (a|b)*abb has a well known minimal DFA with 4 nodes
+        let dfa = compile("/(a|b)*abb/");
+        assert_eq!(dfa.data.len(), 4);
+        assert!(dfa.matches("babb"));
+        assert!(!dfa.matches("abab"));
+    }
+
+    #[test]
+    fn test_step4_empty_language() {
+        let dfa = compile(r"/a[^\s\S]/");
+        assert_eq!(dfa.data.len(), 1);
+        assert!(!dfa.matches(""));
+    }
+
+    #[test]
+    fn test_compile_traced() {
+        let trace = compile_traced("/(a|b)*abb/");
+        let steps: Vec<&str> = trace.stats.iter().map(|stats| stats.step).collect();
+        assert_eq!(steps, vec!["ε-NFA", "NFA", "DFA", "minimized DFA"]);
+        assert_eq!(trace.stats[3].states, 4);
+        assert_eq!(trace.stats[3].edges, 8);
+        assert_eq!(trace.stats[0].states, trace.epsilon_nfa.data.len());
+        assert!(trace.stats[1].states <= trace.stats[0].states);
+        assert_eq!(trace.minimized.equivalent(&trace.dfa), Ok(()));
+        assert!(trace.epsilon_nfa.to_dot().contains("style=dashed"));
+        assert!(!trace.nfa.to_dot().contains("style=dashed"));
+        assert!(trace.to_string().starts_with("step"));
+        assert_eq!(trace.to_string().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_try_compile() {
+        assert!(try_compile("/(a|b)*abb/").unwrap().matches("babb"));
+        assert_eq!(try_compile("/(a/").err(), Some(Error::syntax("unclosed capture group")));
+        assert_eq!(try_compile("/(a|b)*a(a|b){13}/").err(), Some(Error::TooBig { stage: Stage::Dfa, limit: 10_000 }));
+        assert_eq!(try_compile_traced("/a{1001}/").err(), Some(Error::TooBig { stage: Stage::Repetition, limit: 1000 }));
+        assert_eq!(try_compile_traced("/ab/").unwrap().stats.len(), 4);
+    }
+}
@@ -0,0 +1,236 @@
+use automata::StateId;
+use dense::DenseDFA;
+use error::Error;
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+use steps::step2;
+use steps::step4;
+use steps::try_step1;
+use steps::try_step3;
+use steps::Limits;
+
+// Searches input that arrives in chunks, such as a large file or a socket, without keeping it in memory.
+//
+// Uses an unanchored DFA, so it is in a terminal node exactly where some match ends. The state is kept
+// between calls to feed, together with any bytes at the end of a chunk that start a UTF-8 character
+// finished by the next chunk. Bytes that aren't valid UTF-8 can't be part of a match.
+pub struct DfaStream {
+    dfa: Arc<DenseDFA>,
+    state: StateId,
+    // Number of bytes read so far, not counting pending
+    offset: usize,
+    // The start of a UTF-8 character that continues in the next chunk
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl DfaStream {
+    // @param regex looks like: "/[a-z]+/i", the same as for Regex::new.
+    // Panics if the regex isn't valid or goes over the default limits of RegexBuilder.
+    pub fn new(regex: &str) -> Self {
+        DfaStream::try_new(regex).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like new, but returns an error instead of panicking.
+    pub fn try_new(regex: &str) -> Result<Self, Error> {
+        let limits = Limits::default();
+        let nfa = try_step1(regex.to_string(), &[], &limits)?;
+        let dfa = step4(try_step3(step2(nfa.unanchored()), limits.dfa_size)?);
+        Ok(DfaStream::with_dfa(Arc::new(DenseDFA::new(&dfa, true))))
+    }
+
+    // A stream over a DFA that was already built, which has to be in a terminal node wherever a match ends.
+    // The stream never goes back to the start, so the DFA may only reach its dead state after its first match:
+    // the unanchored DFA from try_new never dies, and the leftmost DFA of a Regex only dies once it has matched,
+    // which is as far as is_match_reader reads.
+    pub(crate) fn with_dfa(dfa: Arc<DenseDFA>) -> Self {
+        DfaStream {
+            dfa,
+            state: StateId::new(0),
+            offset: 0,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    // Reads the next chunk of the input, and returns the offset (in bytes from the very start of the input)
+    // of every match that ends in it. A match that ends at offset 0 is reported by the first call.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<usize> {
+        let mut ends: Vec<usize> = self.start();
+        let mut pending = std::mem::take(&mut self.pending);
+        decode_chunk(&mut pending, chunk, |piece| match piece {
+            Ok(text) => self.read(text, &mut ends),
+            Err(len) => self.skip_invalid(len, &mut ends),
+        });
+        self.pending = pending;
+        ends
+    }
+
+    // Ends the input. A character that was cut off at the end is treated as invalid UTF-8.
+    pub fn finish(&mut self) -> Vec<usize> {
+        let mut ends: Vec<usize> = self.start();
+        let len = std::mem::take(&mut self.pending).len();
+        if len > 0 {
+            self.skip_invalid(len, &mut ends);
+        }
+        ends
+    }
+
+    // Number of bytes fed so far.
+    pub fn offset(&self) -> usize {
+        self.offset + self.pending.len()
+    }
+
+    // Goes back to the start, to search a new input.
+    pub fn reset(&mut self) {
+        self.state = self.dfa.start_state();
+        self.offset = 0;
+        self.pending.clear();
+        self.started = false;
+    }
+
+    // Feeds everything the reader gives, stopping as soon as a match is found.
+    pub fn is_match_reader<R: Read>(&mut self, reader: R) -> io::Result<bool> {
+        if read_chunks(reader, |chunk| !self.feed(chunk).is_empty())? {
+            return Ok(true);
+        }
+        Ok(!self.finish().is_empty())
+    }
+
+    // Reports the empty match at offset 0, the first time it is called.
+    fn start(&mut self) -> Vec<usize> {
+        if self.started {
+            return Vec::new();
+        }
+        self.started = true;
+        if self.dfa.is_match_state(self.dfa.start_state()) { vec![0] } else { Vec::new() }
+    }
+
+    fn read(&mut self, text: &str, ends: &mut Vec<usize>) {
+        for ch in text.chars() {
+            // The dead state is only reached after a match, @see with_dfa
+            self.state = self.dfa.next_state(self.state, ch);
+            self.offset += ch.len_utf8();
+            if self.dfa.is_match_state(self.state) {
+                ends.push(self.offset);
+            }
+        }
+    }
+
+    fn skip_invalid(&mut self, len: usize, ends: &mut Vec<usize>) {
+        self.state = self.dfa.start_state();
+        self.offset += len;
+        if self.dfa.is_match_state(self.state) {
+            ends.push(self.offset);
+        }
+    }
+}
+
+// Splits pending followed by chunk into valid UTF-8 text and runs of invalid bytes (given by their length),
+// and passes them to visit in order. A character that the chunk cuts off is left in pending.
+pub(crate) fn decode_chunk<F: FnMut(Result<&str, usize>)>(pending: &mut Vec<u8>, chunk: &[u8], mut visit: F) {
+    let mut bytes = std::mem::take(pending);
+    bytes.extend_from_slice(chunk);
+    let mut rest: &[u8] = &bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                visit(Ok(text));
+                rest = &[];
+                break;
+            }
+            Err(error) => {
+                let (valid, after) = rest.split_at(error.valid_up_to());
+                visit(Ok(std::str::from_utf8(valid).unwrap()));
+                match error.error_len() {
+                    Some(len) => {
+                        visit(Err(len));
+                        rest = &after[len..];
+                    }
+                    // The last character isn't complete yet
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    *pending = rest.to_vec();
+}
+
+// Passes everything the reader gives to visit, one chunk at a time, until visit returns true.
+// Returns whether it did.
+pub(crate) fn read_chunks<R: Read, F: FnMut(&[u8]) -> bool>(mut reader: R, mut visit: F) -> io::Result<bool> {
+    let mut buffer = [0u8; 8192];
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(false),
+            Ok(len) => len,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if visit(&buffer[..len]) {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_stream_chunks() {
+        let mut stream = DfaStream::new("/error/");
+        assert_eq!(stream.feed(b"no err"), vec![]);
+        assert_eq!(stream.feed(b"or here, "), vec![8]);
+        assert_eq!(stream.feed(b"error"), vec![20]);
+        assert_eq!(stream.offset(), 20);
+        assert_eq!(stream.finish(), vec![]);
+        stream.reset();
+        assert_eq!(stream.feed(b"error"), vec![5]);
+        assert_eq!(DfaStream::try_new("/(a/").err(), Some(Error::syntax("unclosed capture group")));
+    }
+
+    #[test]
+    fn test_stream_every_end() {
+        let mut stream = DfaStream::new("/a+/");
+        assert_eq!(stream.feed(b"baab"), vec![2, 3]);
+        let mut empty = DfaStream::new("/x*/");
+        assert_eq!(empty.feed(b""), vec![0]);
+        assert_eq!(empty.feed(b"y"), vec![1]);
+    }
+
+    #[test]
+    fn test_stream_split_utf8() {
+        let text = "naïve café".as_bytes();
+        let mut stream = DfaStream::new("/é/");
+        let mut ends: Vec<usize> = Vec::new();
+        for chunk in text.chunks(1) {
+            ends.extend(stream.feed(chunk));
+        }
+        assert_eq!(ends, vec![text.len()]);
+        // invalid bytes never match, and don't hide what comes after them
+        let mut stream = DfaStream::new("/ab/");
+        assert_eq!(stream.feed(b"a\xffab\xe2"), vec![4]);
+        assert_eq!(stream.finish(), vec![]);
+        assert_eq!(stream.offset(), 5);
+    }
+
+    #[test]
+    fn test_is_match_reader() {
+        let log = "line 1\nline 2\nERROR: disk full\nline 4\n".repeat(1000);
+        assert!(Regex::new(r"/ERROR: \w+/").is_match_reader(log.as_bytes()).unwrap());
+        assert!(!Regex::new("/WARN/").is_match_reader(log.as_bytes()).unwrap());
+        assert!(Regex::new("/z*/").is_match_reader(io::empty()).unwrap());
+        // the DFA of the regex is reused, so the search is the same every time
+        let regex = Regex::new("/ab|b+c/");
+        for _ in 0..3 {
+            assert!(regex.is_match_reader(&b"a\xffabbc"[..]).unwrap());
+            assert!(!regex.is_match_reader(&b"a\xffbb"[..]).unwrap());
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// Finds a fixed string with Boyer–Moore–Horspool, which Regex uses for patterns that only match one string.
+//
+// The needle is compared from its last byte, and on a mismatch the search jumps ahead by how far the
+// haystack byte under the end of the needle is from the needle's own end, so most bytes are never looked at.
+// Both strings are UTF-8, so a match of the bytes always starts and ends on a char boundary.
+pub(crate) struct Finder {
+    needle: Vec<u8>,
+    // How far to move when the byte under the end of the needle is b
+    shift: [usize; 256],
+}
+
+impl Finder {
+    pub(crate) fn new(needle: &str) -> Self {
+        let needle = needle.as_bytes().to_vec();
+        let mut shift = [needle.len(); 256];
+        for (i, b) in needle.iter().enumerate().take(needle.len().saturating_sub(1)) {
+            shift[*b as usize] = needle.len() - 1 - i;
+        }
+        Finder { needle, shift }
+    }
+
+    // Returns the byte offset of the first match that starts at or after pos.
+    pub(crate) fn find(&self, haystack: &str, pos: usize) -> Option<usize> {
+        let haystack = haystack.as_bytes();
+        let len = self.needle.len();
+        if len == 0 {
+            return if pos <= haystack.len() { Some(pos) } else { None };
+        }
+        let mut start = pos;
+        while start + len <= haystack.len() {
+            let last = haystack[start + len - 1];
+            if last == self.needle[len - 1] && haystack[start..start + len - 1] == self.needle[..len - 1] {
+                return Some(start);
+            }
+            start += self.shift[last as usize];
+        }
+        None
+    }
+
+    // Returns the byte offset of the last match that starts at or after pos.
+    pub(crate) fn rfind(&self, haystack: &str, pos: usize) -> Option<usize> {
+        if self.needle.is_empty() {
+            return if pos <= haystack.len() { Some(haystack.len()) } else { None };
+        }
+        let rest = haystack.as_bytes().get(pos..)?;
+        rest.windows(self.needle.len()).rposition(|window| window == self.needle.as_slice()).map(|idx| pos + idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finder() {
+        let finder = Finder::new("abcab");
+        assert_eq!(finder.find("xxabcabcab", 0), Some(2));
+        assert_eq!(finder.find("xxabcabcab", 3), Some(5));
+        assert_eq!(finder.find("xxabcabcab", 6), None);
+        assert_eq!(Finder::new("жы").find("aжaжы", 0), Some(4));
+        assert_eq!(Finder::new("").find("ab", 2), Some(2));
+        assert_eq!(Finder::new("").find("ab", 3), None);
+        assert_eq!(Finder::new("long").find("lon", 0), None);
+        assert_eq!(finder.rfind("xxabcabcab", 0), Some(5));
+        assert_eq!(finder.rfind("xxabcabcab", 6), None);
+    }
+}
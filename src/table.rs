@@ -0,0 +1,184 @@
+use automata::DFA;
+use ranges;
+
+// The formats DFA::to_table can write the transition table in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    // Aligned columns of text
+    Plain,
+    // Like Plain, with the terminal nodes in red using ANSI escape codes
+    Ansi,
+    Markdown,
+    Csv,
+    Html,
+}
+
+impl DFA {
+    // Writes the transition table, with one row per node and one column per character.
+    // Rows are sorted by node id, node 0 is marked with → and terminal nodes with *.
+    // Neighbouring characters that lead to the same node from every row share a column, labelled like "a-z".
+    // Characters that no row connects are left out.
+    pub fn to_table(&self, format: TableFormat) -> String {
+        let mut ids: Vec<usize> = self.data.keys().copied().collect();
+        ids.sort();
+        // Each column is a range of characters and the node each row goes to with them
+        let mut columns: Vec<(char, char, Vec<Option<usize>>)> = Vec::new();
+        for (low, high) in self.alphabet() {
+            let targets: Vec<Option<usize>> = ids.iter().map(|id| self.data[id].follow(&low)).collect();
+            if targets.iter().all(|to| to.is_none()) {
+                continue;
+            }
+            match columns.last_mut() {
+                Some((_, last_high, last)) if ranges::next_char(*last_high) == Some(low) && *last == targets => *last_high = high,
+                _ => columns.push((low, high, targets)),
+            }
+        }
+
+        let mut header: Vec<String> = vec![String::new()];
+        header.extend(columns.iter().map(|(low, high, _)| {
+            if low == high {
+                char_label(*low)
+            } else {
+                format!("{}-{}", char_label(*low), char_label(*high))
+            }
+        }));
+        let missing = match format {
+            TableFormat::Plain | TableFormat::Ansi => "_",
+            _ => "",
+        };
+        let rows: Vec<Vec<String>> = ids.iter().enumerate().map(|(row, id)| {
+            let start = if *id == 0 { "→" } else { "" };
+            let terminal = if self.data[id].is_terminal { "*" } else { "" };
+            let mut cells: Vec<String> = vec![format!("{}{}{}", start, terminal, id)];
+            cells.extend(columns.iter().map(|(_, _, targets)| match targets[row] {
+                Some(to) => to.to_string(),
+                None => missing.to_string(),
+            }));
+            cells
+        }).collect();
+        let terminal: Vec<bool> = ids.iter().map(|id| self.data[id].is_terminal).collect();
+
+        match format {
+            TableFormat::Plain => plain_table(&header, &rows, &[]),
+            TableFormat::Ansi => plain_table(&header, &rows, &terminal),
+            TableFormat::Markdown => markdown_table(&header, &rows),
+            TableFormat::Csv => csv_table(&header, &rows),
+            TableFormat::Html => html_table(&header, &rows, &terminal),
+        }
+    }
+}
+
+// How a character is written in a table header or graph label.
+pub(crate) fn char_label(ch: char) -> String {
+    if ch == ' ' {
+        return "space".to_string();
+    }
+    ch.escape_debug().to_string()
+}
+
+// @param highlight says which rows to color red, and may be empty.
+fn plain_table(header: &[String], rows: &[Vec<String>], highlight: &[bool]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String], red: bool| {
+        let padded: Vec<String> = cells.iter().zip(&widths).enumerate().map(|(i, (cell, width))| {
+            let padding = " ".repeat(width - cell.chars().count());
+            if i == 0 && red {
+                format!("\x1b[0;31m{}\x1b[0m{}", cell, padding)
+            } else if i == 0 {
+                format!("{}{}", cell, padding)
+            } else {
+                format!("{}{}", padding, cell)
+            }
+        }).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let mut lines: Vec<String> = vec![line(header, false)];
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(line(row, highlight.get(i).copied().unwrap_or(false)));
+    }
+    lines.join("\n")
+}
+
+fn markdown_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines: Vec<String> = vec![line(header), format!("|{}", "---|".repeat(header.len()))];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+fn csv_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        }).collect();
+        cells.join(",")
+    };
+    let mut lines: Vec<String> = vec![line(header)];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+fn html_table(header: &[String], rows: &[Vec<String>], terminal: &[bool]) -> String {
+    let escape = |cell: &String| cell.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+    let mut output = String::from("<table>\n<thead>\n<tr>");
+    for cell in header {
+        output += &format!("<th>{}</th>", escape(cell));
+    }
+    output += "</tr>\n</thead>\n<tbody>\n";
+    for (row, is_terminal) in rows.iter().zip(terminal) {
+        output += if *is_terminal { "<tr class=\"terminal\">" } else { "<tr>" };
+        output += &format!("<th>{}</th>", escape(&row[0]));
+        for cell in &row[1..] {
+            output += &format!("<td>{}</td>", escape(cell));
+        }
+        output += "</tr>\n";
+    }
+    output + "</tbody>\n</table>"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steps::compile;
+
+    #[test]
+    fn test_table_plain() {
+        let dfa = compile("/[a-c]x|d/");
+        assert_eq!(dfa.to_table(TableFormat::Plain), [
+            "    a-c  d  x",
+            "→0    1  2  _",
+            "1     _  _  2",
+            "*2    _  _  _",
+        ].join("\n"));
+        // the same every time
+        assert_eq!(dfa.to_table(TableFormat::Plain), compile("/[a-c]x|d/").to_table(TableFormat::Plain));
+    }
+
+    #[test]
+    fn test_table_formats() {
+        let dfa = compile("/a|b,/");
+        assert_eq!(dfa.to_table(TableFormat::Markdown), [
+            "|  | , | a | b |",
+            "|---|---|---|---|",
+            "| →0 |  | 1 | 2 |",
+            "| *1 |  |  |  |",
+            "| 2 | 1 |  |  |",
+        ].join("\n"));
+        assert_eq!(dfa.to_table(TableFormat::Csv), ",\",\",a,b\n→0,,1,2\n*1,,,\n2,1,,");
+        let html = dfa.to_table(TableFormat::Html);
+        assert!(html.contains("<tr class=\"terminal\"><th>*1</th><td></td><td></td><td></td></tr>"));
+        assert!(dfa.to_table(TableFormat::Ansi).contains("\x1b[0;31m*1\x1b[0m"));
+    }
+}
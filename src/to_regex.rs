@@ -0,0 +1,299 @@
+use automata::DFA;
+use parse_regex::escape_char;
+use parse_regex::escape_chars;
+use parse_regex::negate;
+use ranges;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// Turns a DFA back into a regex, the reverse of steps 1 to 4.
+//
+// Uses state elimination: every connection is labelled with a regex, and the nodes are removed one
+// at a time, replacing each path through a removed node with a single connection that spells it out.
+// The labels are built with the constructors below, which simplify as they go so the result stays short.
+impl DFA {
+    // Returns a regex like "/a(b|c)*/" that accepts the same strings as this DFA.
+    // It has no flags, and compiling it gives back an equivalent DFA.
+    pub fn to_regex(&self) -> String {
+        // The DFA nodes keep their ids, with an extra front and terminal node around them
+        let front = self.data.len();
+        let end = front + 1;
+        let mut labels: HashMap<(usize, usize), Expr> = HashMap::new();
+        for (id, node) in &self.data {
+            for (low, high, to) in &node.paths {
+                let label = labels.remove(&(*id, *to)).unwrap_or(Expr::Empty);
+                labels.insert((*id, *to), alt(label, Expr::Chars(vec![(*low, *high)])));
+            }
+            if node.is_terminal {
+                labels.insert((*id, end), Expr::Epsilon);
+            }
+        }
+        labels.insert((front, 0), Expr::Epsilon);
+
+        let mut remaining: BTreeSet<usize> = self.data.keys().copied().collect();
+        while let Some(id) = cheapest_node(&labels, &remaining) {
+            remaining.remove(&id);
+            let repeat = star(labels.remove(&(id, id)).unwrap_or(Expr::Empty));
+            let incoming: Vec<(usize, Expr)> = labels.iter()
+                .filter(|((_, to), _)| *to == id)
+                .map(|((from, _), label)| (*from, label.clone()))
+                .collect();
+            let outgoing: Vec<(usize, Expr)> = labels.iter()
+                .filter(|((from, _), _)| *from == id)
+                .map(|((_, to), label)| (*to, label.clone()))
+                .collect();
+            labels.retain(|(from, to), _| *from != id && *to != id);
+            for (from, before) in &incoming {
+                for (to, after) in &outgoing {
+                    let through = concat(before.clone(), concat(repeat.clone(), after.clone()));
+                    let label = labels.remove(&(*from, *to)).unwrap_or(Expr::Empty);
+                    labels.insert((*from, *to), alt(label, through));
+                }
+            }
+        }
+        format!("/{}/", labels.remove(&(front, end)).unwrap_or(Expr::Empty))
+    }
+}
+
+// Picks the node whose removal adds the fewest connections, which keeps the labels small.
+fn cheapest_node(labels: &HashMap<(usize, usize), Expr>, remaining: &BTreeSet<usize>) -> Option<usize> {
+    remaining.iter().copied().min_by_key(|id| {
+        let incoming = labels.keys().filter(|(from, to)| to == id && from != id).count();
+        let outgoing = labels.keys().filter(|(from, to)| from == id && to != id).count();
+        incoming * outgoing
+    })
+}
+
+// A regex over the DFA's characters. Empty accepts nothing and Epsilon only the empty string.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Expr {
+    Empty,
+    Epsilon,
+    // Sorted, non-overlapping (low, high) ranges
+    Chars(Vec<(char, char)>),
+    Concat(Vec<Expr>),
+    Alt(Vec<Expr>),
+    Star(Box<Expr>),
+}
+
+impl Expr {
+    fn accepts_empty(&self) -> bool {
+        match self {
+            Expr::Empty | Expr::Chars(_) => false,
+            Expr::Epsilon | Expr::Star(_) => true,
+            Expr::Concat(items) => items.iter().all(Expr::accepts_empty),
+            Expr::Alt(items) => items.iter().any(Expr::accepts_empty),
+        }
+    }
+
+    // Splits off the first item of a concatenation, for factoring out common prefixes.
+    fn split_first(self) -> (Expr, Expr) {
+        match self {
+            Expr::Concat(mut items) => {
+                let first = items.remove(0);
+                (first, items.into_iter().fold(Expr::Epsilon, concat))
+            }
+            other => (other, Expr::Epsilon),
+        }
+    }
+}
+
+fn concat(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Empty, _) | (_, Expr::Empty) => Expr::Empty,
+        (Expr::Epsilon, other) | (other, Expr::Epsilon) => other,
+        (a, b) => {
+            let mut items: Vec<Expr> = Vec::new();
+            for expr in [a, b] {
+                match expr {
+                    Expr::Concat(inner) => items.extend(inner),
+                    other => items.push(other),
+                }
+            }
+            Expr::Concat(items)
+        }
+    }
+}
+
+// Single characters are merged into one class, duplicates are dropped, ε is dropped when another
+// alternative already accepts the empty string, and alternatives with the same start are factored.
+fn alt(a: Expr, b: Expr) -> Expr {
+    let mut chars: Vec<(char, char)> = Vec::new();
+    let mut items: Vec<Expr> = Vec::new();
+    for expr in [a, b] {
+        let exprs = match expr {
+            Expr::Alt(inner) => inner,
+            other => vec![other],
+        };
+        for expr in exprs {
+            match expr {
+                Expr::Empty => {}
+                Expr::Chars(set) => chars.extend(set),
+                other => items.push(other),
+            }
+        }
+    }
+    if !chars.is_empty() {
+        items.push(Expr::Chars(ranges::normalize(chars)));
+    }
+    items.sort();
+    items.dedup();
+    if items.len() > 1 && items.iter().any(|item| *item != Expr::Epsilon && item.accepts_empty()) {
+        items.retain(|item| *item != Expr::Epsilon);
+    }
+
+    let mut factored: Vec<(Expr, Vec<Expr>)> = Vec::new();
+    for item in items {
+        let (first, rest) = item.split_first();
+        match factored.iter_mut().find(|(other, _)| *other == first) {
+            Some((_, rests)) => rests.push(rest),
+            None => factored.push((first, vec![rest])),
+        }
+    }
+    if factored.iter().any(|(_, rests)| rests.len() > 1) {
+        return factored.into_iter()
+            .map(|(first, rests)| concat(first, rests.into_iter().fold(Expr::Empty, alt)))
+            .fold(Expr::Empty, alt);
+    }
+
+    let mut items: Vec<Expr> = factored.into_iter().map(|(first, mut rests)| concat(first, rests.remove(0))).collect();
+    match items.len() {
+        0 => Expr::Empty,
+        1 => items.remove(0),
+        _ => Expr::Alt(items),
+    }
+}
+
+fn star(expr: Expr) -> Expr {
+    match expr {
+        Expr::Empty | Expr::Epsilon => Expr::Epsilon,
+        Expr::Star(_) => expr,
+        // (x|ε)* is the same as x*
+        Expr::Alt(items) if items.contains(&Expr::Epsilon) => {
+            star(items.into_iter().filter(|item| *item != Expr::Epsilon).fold(Expr::Empty, alt))
+        }
+        other => Expr::Star(Box::new(other)),
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            // A class that can't match anything
+            Expr::Empty => write!(f, r"[^\s\S]"),
+            Expr::Epsilon => Ok(()),
+            Expr::Chars(set) => write!(f, "{}", class(set)),
+            Expr::Concat(items) => {
+                let mut index = 0;
+                while index < items.len() {
+                    let item = &items[index];
+                    // x followed by x* is written as x+
+                    if items.get(index + 1) == Some(&Expr::Star(Box::new(item.clone()))) {
+                        write!(f, "{}+", Atom(item))?;
+                        index += 2;
+                        continue;
+                    }
+                    match item {
+                        Expr::Alt(alternatives) if !alternatives.contains(&Expr::Epsilon) => write!(f, "{}", Atom(item))?,
+                        _ => write!(f, "{}", item)?,
+                    }
+                    index += 1;
+                }
+                Ok(())
+            }
+            Expr::Alt(items) if items.contains(&Expr::Epsilon) => {
+                let others = items.iter().filter(|item| **item != Expr::Epsilon).cloned().fold(Expr::Empty, alt);
+                write!(f, "{}?", Atom(&others))
+            }
+            Expr::Alt(items) => {
+                let parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "{}", parts.join("|"))
+            }
+            Expr::Star(inner) => write!(f, "{}*", Atom(inner)),
+        }
+    }
+}
+
+// An expression that a quantifier can follow: a class, or anything else in parentheses.
+struct Atom<'e>(&'e Expr);
+
+impl<'e> std::fmt::Display for Atom<'e> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Expr::Chars(_) | Expr::Empty => write!(f, "{}", self.0),
+            other => write!(f, "({})", other),
+        }
+    }
+}
+
+// Writes a set of characters as a single character, a shorthand like \d or ., or a character class.
+fn class(set: &[(char, char)]) -> String {
+    if let [(low, high)] = set {
+        if low == high {
+            return escape_char(*low, false);
+        }
+    }
+    if set == negate(&[('\n', '\n')], &HashSet::new()) {
+        return ".".to_string();
+    }
+    for shorthand in ['d', 'w', 's', 'D', 'W', 'S'] {
+        if set == escape_chars(shorthand, &HashSet::new()) {
+            return format!("\\{}", shorthand);
+        }
+    }
+    let complement = negate(set, &HashSet::new());
+    if complement.is_empty() {
+        return r"[\s\S]".to_string();
+    }
+    if complement.len() < set.len() {
+        return format!("[^{}]", class_items(&complement));
+    }
+    format!("[{}]", class_items(set))
+}
+
+fn class_items(set: &[(char, char)]) -> String {
+    set.iter().map(|(low, high)| {
+        let (low, high) = (*low, *high);
+        match ranges::range_len(low, high) {
+            1 => escape_char(low, true),
+            2 => format!("{}{}", escape_char(low, true), escape_char(high, true)),
+            _ => format!("{}-{}", escape_char(low, true), escape_char(high, true)),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+
+    fn round_trip(regex: &str) -> String {
+        let dfa = compile(regex);
+        let back = dfa.to_regex();
+        assert_eq!(compile(&back).equivalent(&dfa), Ok(()), "{} became {}", regex, back);
+        back
+    }
+
+    #[test]
+    fn test_to_regex_round_trip() {
+        for regex in ["/(a|b)*abb/", r"/\d+(\.\d*)?/", "/(ab|cd)*e?/", "/a{2,4}/", "/[^a-c]x|y+/",
+                      "/(foo|bar)+baz/", r"/[\]\-^]\*\\/", "/colou?r/i", r"/a\n\t./", "//"] {
+            round_trip(regex);
+        }
+    }
+
+    #[test]
+    fn test_to_regex_simplifies() {
+        assert_eq!(round_trip("/ab*c/"), "/ab*c/");
+        assert_eq!(round_trip("/a|b|c|d/"), "/[a-d]/");
+        assert_eq!(round_trip("/aa*/"), "/a+/");
+        assert_eq!(round_trip(r"/[0-9]+/"), r"/\d+/");
+        assert_eq!(round_trip("/abc|abd/"), "/ab[cd]/");
+        assert_eq!(round_trip("/xy|x/"), "/xy?/");
+    }
+
+    #[test]
+    fn test_to_regex_empty_language() {
+        assert_eq!(round_trip(r"/a[^\s\S]/"), r"/[^\s\S]/");
+    }
+}
@@ -0,0 +1,168 @@
+use automata::DFA;
+use automata::NFA;
+use ranges;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Every step an automaton took on an input, returned by DFA::trace and NFA::trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub regex: String,
+    pub input: String,
+    pub steps: Vec<TraceStep>,
+    pub accepted: bool,
+    // Length in bytes of the part of the input that was read before the automaton got stuck,
+    // which is the whole input unless a character had no connection
+    pub consumed: usize,
+    // The characters that had a connection at the point where the input was rejected,
+    // as sorted (low, high) ranges
+    pub expected: Vec<(char, char)>,
+}
+
+// One character read. For a DFA, from and to hold a single node; for an NFA they hold every active node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    // Byte offset of the character in the input
+    pub position: usize,
+    pub ch: char,
+    pub from: Vec<usize>,
+    pub to: Vec<usize>,
+}
+
+impl DFA {
+    // Runs the DFA like matches, recording every step.
+    pub fn trace(&self, input: &str) -> Trace {
+        let mut steps: Vec<TraceStep> = Vec::new();
+        let mut id: usize = 0;
+        let mut consumed = input.len();
+        for (position, ch) in input.char_indices() {
+            match self.data[&id].follow(&ch) {
+                Some(to) => {
+                    steps.push(TraceStep { position, ch, from: vec![id], to: vec![to] });
+                    id = to;
+                }
+                None => {
+                    consumed = position;
+                    break;
+                }
+            }
+        }
+        let accepted = consumed == input.len() && self.data[&id].is_terminal;
+        let expected = if accepted { Vec::new() } else { ranges::normalize(self.data[&id].paths.iter().map(|(low, high, _)| (*low, *high)).collect()) };
+        Trace { regex: self.regex.clone(), input: input.to_string(), steps, accepted, consumed, expected }
+    }
+}
+
+impl NFA {
+    // Simulates the NFA on the input, keeping the set of active nodes and recording every step.
+    // The active nodes include everything reachable with ε connections.
+    pub fn trace(&self, input: &str) -> Trace {
+        let mut steps: Vec<TraceStep> = Vec::new();
+        let mut active: Vec<usize> = self.closure(&[0]);
+        let mut consumed = input.len();
+        for (position, ch) in input.char_indices() {
+            let next: Vec<usize> = active.iter()
+                .flat_map(|id| self.data[id].paths.iter())
+                .filter(|(low, high, _)| (*low..=*high).contains(&ch))
+                .map(|(_, _, to)| *to)
+                .collect();
+            if next.is_empty() {
+                consumed = position;
+                break;
+            }
+            let next = self.closure(&next);
+            steps.push(TraceStep { position, ch, from: active, to: next.clone() });
+            active = next;
+        }
+        let accepted = consumed == input.len() && active.iter().any(|id| self.data[id].is_terminal);
+        let expected = if accepted {
+            Vec::new()
+        } else {
+            ranges::normalize(active.iter().flat_map(|id| self.data[id].paths.iter()).map(|(low, high, _)| (*low, *high)).collect())
+        };
+        Trace { regex: self.regex.clone(), input: input.to_string(), steps, accepted, consumed, expected }
+    }
+
+    // All the nodes reachable from ids with ε connections, sorted.
+    fn closure(&self, ids: &[usize]) -> Vec<usize> {
+        sorted(ids.iter().flat_map(|id| self.epsilon_closure(*id)))
+    }
+}
+
+fn sorted<T: Ord, I: Iterator<Item = T>>(items: I) -> Vec<T> {
+    items.collect::<BTreeSet<T>>().into_iter().collect()
+}
+
+// One line per step, then the verdict. Node sets are written like {0, 3}.
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nodes = |ids: &[usize]| {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+            format!("{{{}}}", ids.join(", "))
+        };
+        writeln!(f, "{} on {:?}", self.regex, self.input)?;
+        for step in &self.steps {
+            writeln!(f, "{:>4}  {:<6} {} -> {}", step.position, format!("{:?}", step.ch), nodes(&step.from), nodes(&step.to))?;
+        }
+        if self.accepted {
+            return write!(f, "accepted");
+        }
+        let read = &self.input[..self.consumed];
+        match self.input[self.consumed..].chars().next() {
+            Some(ch) => write!(f, "rejected: read {:?}, then {:?} has no connection", read, ch)?,
+            None => write!(f, "rejected: the input ended after {:?}", read)?,
+        }
+        if self.expected.is_empty() {
+            return write!(f, "; nothing more can be read");
+        }
+        let expected: Vec<String> = self.expected.iter().map(|(low, high)| {
+            if low == high { format!("{:?}", low) } else { format!("{:?}-{:?}", low, high) }
+        }).collect();
+        write!(f, "; expected one of {}", expected.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use steps::compile;
+    use steps::compile_traced;
+
+    #[test]
+    fn test_dfa_trace() {
+        let dfa = compile("/ab+c/");
+        let trace = dfa.trace("abbc");
+        assert!(trace.accepted);
+        assert_eq!(trace.steps.len(), 4);
+        assert_eq!((trace.steps[2].position, trace.steps[2].ch), (2, 'b'));
+        assert_eq!(trace.steps[2].from, trace.steps[2].to);
+
+        let trace = dfa.trace("abx");
+        assert!(!trace.accepted);
+        assert_eq!(trace.consumed, 2);
+        assert_eq!(trace.expected, vec![('b', 'c')]);
+        assert_eq!(trace.to_string(), [
+            "/ab+c/ on \"abx\"",
+            "   0  'a'    {0} -> {1}",
+            "   1  'b'    {1} -> {2}",
+            "rejected: read \"ab\", then 'x' has no connection; expected one of 'b'-'c'",
+        ].join("\n"));
+        assert!(dfa.trace("ab").to_string().ends_with("the input ended after \"ab\"; expected one of 'b'-'c'"));
+        assert!(dfa.trace("abcd").to_string().ends_with("'d' has no connection; nothing more can be read"));
+    }
+
+    #[test]
+    fn test_nfa_trace() {
+        let trace = compile_traced("/a*ab/");
+        let nfa_trace = trace.epsilon_nfa.trace("aab");
+        assert!(nfa_trace.accepted);
+        assert_eq!(nfa_trace.steps.len(), 3);
+        // after the first a it could still be in the a* loop or past it
+        assert!(nfa_trace.steps[0].to.len() > 1);
+        assert_eq!(nfa_trace.accepted, trace.minimized.trace("aab").accepted);
+
+        let rejected = trace.nfa.trace("aac");
+        assert!(!rejected.accepted);
+        assert_eq!(rejected.consumed, 2);
+        assert_eq!(rejected.expected, vec![('a', 'b')]);
+    }
+}